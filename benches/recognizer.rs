@@ -0,0 +1,76 @@
+//! Compare the compiled segment tree router with the NFA router on large
+//! route sets, run with `cargo bench`.
+extern crate sapper;
+
+use std::time::Instant;
+
+use sapper::recognizer::{NfaRouter, Router};
+
+const ROUNDS: usize = 200;
+
+// `n` resources, each with the usual REST routes, plus some catch-alls
+fn routes(n: usize) -> Vec<String> {
+    let mut routes = Vec::new();
+    for i in 0..n {
+        routes.push(format!("/api/resource{}", i));
+        routes.push(format!("/api/resource{}/new", i));
+        routes.push(format!("/api/resource{}/:id", i));
+        routes.push(format!("/api/resource{}/:id/edit", i));
+        routes.push(format!("/api/resource{}/:id/items/:item_id", i));
+    }
+    routes.push("/static/*path".to_owned());
+    routes.push("*catchall".to_owned());
+    routes
+}
+
+fn paths(n: usize) -> Vec<String> {
+    let mut paths = Vec::new();
+    for i in (0..n).step_by(7) {
+        paths.push(format!("/api/resource{}", i));
+        paths.push(format!("/api/resource{}/new", i));
+        paths.push(format!("/api/resource{}/42/edit", i));
+        paths.push(format!("/api/resource{}/42/items/7", i));
+    }
+    paths.push("/static/css/site.css".to_owned());
+    paths.push("/not/a/route".to_owned());
+    paths
+}
+
+fn bench<F: FnMut(&str) -> bool>(name: &str, paths: &[String], mut recognize: F) {
+    let start = Instant::now();
+    let mut found = 0;
+    for _ in 0..ROUNDS {
+        for path in paths.iter() {
+            if recognize(path) {
+                found += 1;
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    let lookups = (ROUNDS * paths.len()) as u32;
+    println!(
+        "{:<12} {:>10.2?} per lookup ({} lookups, {} matched)",
+        name,
+        elapsed / lookups,
+        lookups,
+        found
+    );
+}
+
+fn main() {
+    for &n in [20, 100, 300].iter() {
+        let routes = routes(n);
+        let paths = paths(n);
+
+        let mut router = Router::new();
+        let mut nfa_router = NfaRouter::new();
+        for route in routes.iter() {
+            router.add(route, ());
+            nfa_router.add(route, ());
+        }
+
+        println!("{} routes:", routes.len());
+        bench("tree", &paths, |path| router.recognize(path).is_ok());
+        bench("nfa", &paths, |path| nfa_router.recognize(path).is_ok());
+    }
+}
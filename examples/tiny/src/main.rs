@@ -11,5 +11,5 @@ pub fn main() {
         .add_module(Box::new(bar::Bar));
 
     println!("Listening on http://127.0.0.1:1337");
-    sapp.run_http();
+    sapp.run_http().unwrap();
 }
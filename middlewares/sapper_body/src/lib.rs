@@ -28,7 +28,7 @@ pub fn parse(req: &mut Request) -> Result<()> {
             let typenum = req
                 .headers()
                 .get::<ContentType>()
-                .map(|header| match **header {
+                .map(|header| match header.0 {
                     mime::Mime(mime::TopLevel::Application, mime::SubLevel::Json, _) => 1,
                     _ => 0,
                 })
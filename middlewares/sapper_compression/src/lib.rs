@@ -0,0 +1,329 @@
+use std::io::{Read, Write};
+
+use flate2::read::{GzEncoder, ZlibEncoder};
+use flate2::write::{GzEncoder as GzWriter, ZlibEncoder as ZlibWriter};
+use flate2::Compression;
+use sapper::status::StatusCode;
+use sapper::{Armor, Error, Request, Response, Result};
+
+// the codings offered, the first is preferred when the client likes
+// several as much
+const ENCODINGS: [&str; 4] = ["br", "gzip", "deflate", "identity"];
+
+// brotli quality and window, the higher qualities are too slow for
+// responses made on each request
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+const BUFFER_SIZE: usize = 4096;
+
+/// Response compression: the bodies of compressible types are compressed
+/// by the coding the client prefers in `Accept-Encoding`, `br`, `gzip` or
+/// `deflate`.
+///
+/// Buffered bodies are compressed when as large as the minimum size,
+/// streamed bodies always, as they are read. Responses which already have
+/// a `Content-Encoding` are left alone, and so are the types which are
+/// compressed already, images, videos or archives. Server sent events,
+/// `text/event-stream`, are never compressed, a compressor holds the events
+/// back until it has a block to send.
+///
+/// ```ignore
+/// app.with_armor(Box::new(CompressionArmor::new().min_size(512)));
+/// ```
+pub struct CompressionArmor {
+    min_size: usize,
+    level: u32,
+    types: Vec<String>,
+}
+
+impl Default for CompressionArmor {
+    fn default() -> CompressionArmor {
+        CompressionArmor::new()
+    }
+}
+
+impl CompressionArmor {
+    /// compress bodies of 1 KiB at least, at level 6, of the text types,
+    /// json, javascript, xml and svg
+    pub fn new() -> CompressionArmor {
+        CompressionArmor {
+            min_size: 1024,
+            level: 6,
+            types: vec![
+                "text/*".to_owned(),
+                "application/json".to_owned(),
+                "application/*+json".to_owned(),
+                "application/javascript".to_owned(),
+                "application/xml".to_owned(),
+                "application/*+xml".to_owned(),
+                "application/wasm".to_owned(),
+                "image/svg+xml".to_owned(),
+            ],
+        }
+    }
+
+    /// the size under which buffered bodies are sent as they are, the
+    /// coding costs more than it saves on them
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// the gzip and deflate level, from 0, none, to 9, the best
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// compress only the media types in `types`, a `*` matches any part of
+    /// them, `text/*` or `application/*+json`
+    pub fn content_types(mut self, types: &[&str]) -> Self {
+        self.types = types.iter().map(|t| t.to_ascii_lowercase()).collect();
+        self
+    }
+
+    fn compressible(&self, content_type: &str) -> bool {
+        let media = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        if media == "text/event-stream" {
+            return false;
+        }
+        self.types.iter().any(|pattern| match pattern.find('*') {
+            Some(star) => {
+                media.len() > star
+                    && media.starts_with(&pattern[..star])
+                    && media.ends_with(&pattern[star + 1..])
+            }
+            None => media == *pattern,
+        })
+    }
+
+    fn compress(&self, encoding: &str, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match encoding {
+            "br" => {
+                let mut out = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(
+                        &mut out,
+                        BUFFER_SIZE,
+                        BROTLI_QUALITY,
+                        BROTLI_WINDOW,
+                    );
+                    writer.write_all(body)?;
+                }
+                Ok(out)
+            }
+            "gzip" => {
+                let mut writer = GzWriter::new(Vec::new(), Compression::new(self.level));
+                writer.write_all(body)?;
+                writer.finish()
+            }
+            _ => {
+                let mut writer = ZlibWriter::new(Vec::new(), Compression::new(self.level));
+                writer.write_all(body)?;
+                writer.finish()
+            }
+        }
+    }
+
+    fn compress_stream(
+        &self,
+        encoding: &str,
+        stream: Box<dyn Read + Send>,
+    ) -> Box<dyn Read + Send> {
+        match encoding {
+            "br" => Box::new(brotli::CompressorReader::new(
+                stream,
+                BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            )),
+            "gzip" => Box::new(GzEncoder::new(stream, Compression::new(self.level))),
+            _ => Box::new(ZlibEncoder::new(stream, Compression::new(self.level))),
+        }
+    }
+}
+
+impl Armor for CompressionArmor {
+    fn before(&self, _req: &mut Request) -> Result<()> {
+        Ok(())
+    }
+
+    fn after(&self, req: &Request, res: &mut Response) -> Result<()> {
+        if res.headers().contains("Content-Encoding")
+            || res.status() == StatusCode::NoContent
+            || res.status() == StatusCode::NotModified
+        {
+            return Ok(());
+        }
+        let compressible = res
+            .headers()
+            .get_str("Content-Type")
+            .map(|content_type| self.compressible(content_type))
+            .unwrap_or(false);
+        if !compressible {
+            return Ok(());
+        }
+
+        // the response differs by the coding accepted, even when it is
+        // not compressed for this one
+        res.headers_mut()
+            .append_raw("Vary", b"Accept-Encoding".to_vec());
+
+        // without the header, a client may not understand any coding
+        if !req.headers().contains("Accept-Encoding") {
+            return Ok(());
+        }
+        let encoding = match req.negotiate_encoding(&ENCODINGS) {
+            Ok("identity") | Err(_) => return Ok(()),
+            Ok(encoding) => encoding,
+        };
+
+        if let Some(stream) = res.take_stream() {
+            res.write_stream(self.compress_stream(encoding, stream));
+        } else {
+            let body = match *res.body() {
+                Some(ref body) if body.len() >= self.min_size => self
+                    .compress(encoding, body)
+                    .map_err(|err| Error::InternalServerError(format!("compression: {}", err)))?,
+                _ => return Ok(()),
+            };
+            res.write_raw_body(body);
+        }
+
+        let headers = res.headers_mut();
+        headers.remove_raw("Content-Length");
+        headers.set_raw("Content-Encoding", vec![encoding.as_bytes().to_vec()]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompressionArmor;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use sapper::{App, Module, Request, Response, Result, Router, TestClient};
+    use std::io::{Cursor, Read};
+
+    fn json() -> String {
+        let items: Vec<String> = (0..200)
+            .map(|i| format!("{{\"id\":{},\"name\":\"item\"}}", i))
+            .collect();
+        format!("[{}]", items.join(","))
+    }
+
+    fn respond(content_type: &str, body: Vec<u8>) -> Response {
+        let mut res = Response::new();
+        res.headers_mut()
+            .set_raw("Content-Type", vec![content_type.as_bytes().to_vec()]);
+        res.write_raw_body(body);
+        res
+    }
+
+    struct Api;
+
+    impl Module for Api {
+        fn router(&self, router: &mut Router) -> Result<()> {
+            router.get("/json", |_: &mut Request| {
+                Ok(respond("application/json", json().into_bytes()))
+            });
+            router.get("/small", |_: &mut Request| {
+                Ok(respond("text/plain; charset=utf-8", b"small".to_vec()))
+            });
+            router.get("/png", |_: &mut Request| {
+                Ok(respond("image/png", vec![0; 4096]))
+            });
+            router.get("/stream", |_: &mut Request| {
+                let mut res = respond("text/plain", Vec::new());
+                res.write_stream(Cursor::new(json().into_bytes()));
+                Ok(res)
+            });
+            router.get("/events", |_: &mut Request| {
+                let mut res = respond("text/event-stream", Vec::new());
+                res.write_stream(Cursor::new(b"data: hello\n\n".to_vec()));
+                Ok(res)
+            });
+            Ok(())
+        }
+    }
+
+    fn client() -> TestClient {
+        let mut app = App::new();
+        app.with_armor(Box::new(CompressionArmor::new()))
+            .add_module(Box::new(Api));
+        TestClient::new(app)
+    }
+
+    fn decode<R: Read>(mut reader: R) -> String {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn it_works() {}
+
+    #[test]
+    fn buffered() {
+        let client = client();
+
+        let res = client
+            .get("/json")
+            .header("Accept-Encoding", "gzip, deflate")
+            .send();
+        assert_eq!(res.header("Content-Encoding"), Some("gzip".to_owned()));
+        assert_eq!(res.header("Vary"), Some("Accept-Encoding".to_owned()));
+        assert!(res.body().len() * 5 < json().len());
+        assert_eq!(decode(GzDecoder::new(res.body())), json());
+
+        let res = client
+            .get("/json")
+            .header("Accept-Encoding", "deflate")
+            .send();
+        assert_eq!(decode(ZlibDecoder::new(res.body())), json());
+
+        let res = client
+            .get("/json")
+            .header("Accept-Encoding", "br;q=1, gzip;q=0.5")
+            .send();
+        assert_eq!(res.header("Content-Encoding"), Some("br".to_owned()));
+        assert_eq!(decode(brotli::Decompressor::new(res.body(), 4096)), json());
+
+        // too small, not accepted, compressed already
+        let res = client
+            .get("/small")
+            .header("Accept-Encoding", "gzip")
+            .send();
+        assert_eq!(res.header("Content-Encoding"), None);
+        assert_eq!(res.header("Vary"), Some("Accept-Encoding".to_owned()));
+        assert_eq!(res.text(), "small");
+        let res = client.get("/json").send();
+        assert_eq!(res.header("Content-Encoding"), None);
+        assert_eq!(res.text(), json());
+        let res = client.get("/png").header("Accept-Encoding", "gzip").send();
+        assert_eq!(res.header("Content-Encoding"), None);
+        assert_eq!(res.header("Vary"), None);
+    }
+
+    #[test]
+    fn streaming() {
+        let res = client()
+            .get("/stream")
+            .header("Accept-Encoding", "gzip")
+            .send();
+        assert_eq!(res.header("Content-Encoding"), Some("gzip".to_owned()));
+        assert_eq!(decode(GzDecoder::new(res.body())), json());
+
+        // events are sent as they come
+        let res = client()
+            .get("/events")
+            .header("Accept-Encoding", "gzip")
+            .send();
+        assert_eq!(res.header("Content-Encoding"), None);
+        assert_eq!(res.text(), "data: hello\n\n");
+    }
+}
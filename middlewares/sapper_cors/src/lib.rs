@@ -0,0 +1,433 @@
+use std::time::Duration;
+
+use regex::Regex;
+use sapper::status::StatusCode;
+use sapper::{Armor, Method, Request, Response, Result};
+
+// How the origins allowed are matched.
+enum AllowOrigin {
+    Any,
+    Exact(String),
+    // `https://*.example.com`, what comes before and after the `*`
+    Wildcard(String, String),
+    Regex(Regex),
+    Predicate(Box<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl AllowOrigin {
+    fn matches(&self, origin: &str) -> bool {
+        match *self {
+            AllowOrigin::Any => true,
+            AllowOrigin::Exact(ref exact) => exact.eq_ignore_ascii_case(origin),
+            AllowOrigin::Wildcard(ref head, ref tail) => {
+                let origin = origin.to_ascii_lowercase();
+                origin.len() > head.len() + tail.len()
+                    && origin.starts_with(head.as_str())
+                    && origin.ends_with(tail.as_str())
+            }
+            AllowOrigin::Regex(ref regex) => regex.is_match(origin),
+            AllowOrigin::Predicate(ref predicate) => predicate(origin),
+        }
+    }
+}
+
+/// Cross origin resource sharing: the `Access-Control-*` headers for the
+/// requests of the origins allowed, preflight `OPTIONS` requests are
+/// answered by the armor itself.
+///
+/// Add it as the first armor of the app, so preflights are answered before
+/// other armors see them; the app answers the `OPTIONS` requests of any
+/// routed path, so preflights reach it for routes of other methods only.
+///
+/// ```ignore
+/// app.with_armor(Box::new(
+///     CorsArmor::new()
+///         .allow_origin("https://app.example.com")
+///         .allow_origin("https://*.example.com")
+///         .allow_headers(&["Content-Type", "X-CSRF-Token"])
+///         .allow_credentials(true)
+///         .max_age(Duration::from_secs(3600)),
+/// ));
+/// ```
+pub struct CorsArmor {
+    origins: Vec<AllowOrigin>,
+    methods: Vec<Method>,
+    // `None` allows the headers a preflight asks for
+    headers: Option<Vec<String>>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for CorsArmor {
+    fn default() -> CorsArmor {
+        CorsArmor::new()
+    }
+}
+
+impl CorsArmor {
+    /// no origin is allowed until added, the methods allowed are `GET`,
+    /// `HEAD`, `POST`, `PUT`, `PATCH` and `DELETE`, with any header
+    pub fn new() -> CorsArmor {
+        CorsArmor {
+            origins: Vec::new(),
+            methods: vec![
+                Method::Get,
+                Method::Head,
+                Method::Post,
+                Method::Put,
+                Method::Patch,
+                Method::Delete,
+            ],
+            headers: None,
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// allow `origin`, `https://app.example.com`, or with a `*` for any
+    /// part of it, `https://*.example.com`, `*` alone is `allow_any_origin`
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        let origin = origin.trim().to_ascii_lowercase();
+        if origin == "*" {
+            return self.allow_any_origin();
+        }
+        let allow = match origin.find('*') {
+            Some(star) => {
+                AllowOrigin::Wildcard(origin[..star].to_owned(), origin[star + 1..].to_owned())
+            }
+            None => AllowOrigin::Exact(origin),
+        };
+        self.origins.push(allow);
+        self
+    }
+
+    /// allow the origins matching `pattern`, panics when it is not a valid
+    /// regex
+    pub fn allow_origin_regex(mut self, pattern: &str) -> Self {
+        let regex = Regex::new(pattern)
+            .unwrap_or_else(|err| panic!("invalid origin regex `{}`: {}", pattern, err));
+        self.origins.push(AllowOrigin::Regex(regex));
+        self
+    }
+
+    /// allow the origins `predicate` accepts
+    pub fn allow_origin_fn<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.origins
+            .push(AllowOrigin::Predicate(Box::new(predicate)));
+        self
+    }
+
+    /// allow any origin, answered `*`, or the origin itself with credentials
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins.push(AllowOrigin::Any);
+        self
+    }
+
+    pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// allow only `headers`, instead of any header
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = Some(headers.iter().map(|h| h.to_ascii_lowercase()).collect());
+        self
+    }
+
+    /// let the scripts of the origin read `headers` of the responses
+    pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+        self.expose_headers = headers.iter().map(|h| (*h).to_owned()).collect();
+        self
+    }
+
+    /// let the origin send cookies and read the responses to them
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// how long the client may cache the answer to a preflight
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn allowed(&self, origin: &str) -> bool {
+        self.origins.iter().any(|allow| allow.matches(origin))
+    }
+
+    // the `Access-Control-Allow-Origin` of an allowed origin, `*` only
+    // when any origin is allowed without credentials
+    fn allow_origin_value(&self, origin: &str) -> String {
+        let any = self
+            .origins
+            .iter()
+            .any(|allow| matches!(*allow, AllowOrigin::Any));
+        if any && !self.credentials {
+            "*".to_owned()
+        } else {
+            origin.to_owned()
+        }
+    }
+
+    fn set_common(&self, origin: &str, res: &mut Response) {
+        let headers = res.headers_mut();
+        headers.set_raw(
+            "Access-Control-Allow-Origin",
+            vec![self.allow_origin_value(origin).into_bytes()],
+        );
+        if self.credentials {
+            headers.set_raw("Access-Control-Allow-Credentials", vec![b"true".to_vec()]);
+        }
+        headers.append_raw("Vary", b"Origin".to_vec());
+    }
+
+    // the answer to a preflight, `None` when it asks for what is not
+    // allowed, so it is answered without any `Access-Control-*` header
+    fn preflight(&self, req: &Request, origin: &str) -> Option<Response> {
+        let method = req
+            .headers()
+            .get_str("Access-Control-Request-Method")?
+            .trim()
+            .parse::<Method>()
+            .ok()?;
+        if !self.methods.contains(&method) {
+            return None;
+        }
+
+        let requested: Vec<String> = req
+            .headers()
+            .get_raw("Access-Control-Request-Headers")
+            .unwrap_or(&[])
+            .iter()
+            .flat_map(|value| {
+                String::from_utf8_lossy(value)
+                    .split(',')
+                    .map(|h| h.trim().to_ascii_lowercase())
+                    .filter(|h| !h.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        if let Some(ref headers) = self.headers {
+            if !requested.iter().all(|h| headers.contains(h)) {
+                return None;
+            }
+        }
+
+        let mut res = Response::new();
+        res.set_status(StatusCode::NoContent);
+        self.set_common(origin, &mut res);
+        let methods: Vec<&str> = self.methods.iter().map(|m| m.as_str()).collect();
+        let headers = res.headers_mut();
+        headers.set_raw(
+            "Access-Control-Allow-Methods",
+            vec![methods.join(", ").into_bytes()],
+        );
+        if !requested.is_empty() {
+            headers.set_raw(
+                "Access-Control-Allow-Headers",
+                vec![requested.join(", ").into_bytes()],
+            );
+        }
+        if let Some(max_age) = self.max_age {
+            headers.set_raw(
+                "Access-Control-Max-Age",
+                vec![max_age.as_secs().to_string().into_bytes()],
+            );
+        }
+        headers.append_raw("Vary", b"Access-Control-Request-Method".to_vec());
+        headers.append_raw("Vary", b"Access-Control-Request-Headers".to_vec());
+        Some(res)
+    }
+}
+
+fn origin(req: &Request) -> Option<&str> {
+    req.headers().get_str("Origin")
+}
+
+fn preflight(req: &Request) -> bool {
+    *req.method() == Method::Options && req.headers().contains("Access-Control-Request-Method")
+}
+
+impl Armor for CorsArmor {
+    fn before(&self, _req: &mut Request) -> Result<()> {
+        Ok(())
+    }
+
+    fn intercept(&self, req: &mut Request) -> Result<Option<Response>> {
+        if !preflight(req) {
+            return Ok(None);
+        }
+        match origin(req) {
+            Some(origin) if self.allowed(origin) => Ok(self.preflight(req, origin)),
+            _ => Ok(None),
+        }
+    }
+
+    fn after(&self, req: &Request, res: &mut Response) -> Result<()> {
+        // a preflight reaching the app was refused
+        if preflight(req) {
+            return Ok(());
+        }
+
+        let origin = match origin(req) {
+            Some(origin) if self.allowed(origin) => origin,
+            // the response differs by origin all the same
+            Some(_) => {
+                res.headers_mut().append_raw("Vary", b"Origin".to_vec());
+                return Ok(());
+            }
+            None => return Ok(()),
+        };
+
+        self.set_common(origin, res);
+        if !self.expose_headers.is_empty() {
+            res.headers_mut().set_raw(
+                "Access-Control-Expose-Headers",
+                vec![self.expose_headers.join(", ").into_bytes()],
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CorsArmor;
+    use sapper::status::StatusCode;
+    use sapper::{App, Method, Module, Request, Response, Result, Router, TestClient};
+    use std::time::Duration;
+
+    struct Api;
+
+    impl Module for Api {
+        fn router(&self, router: &mut Router) -> Result<()> {
+            router.get("/items", |_: &mut Request| {
+                let mut res = Response::new();
+                res.write_body("items".to_owned());
+                Ok(res)
+            });
+            router.post("/items", |_: &mut Request| Ok(Response::new()));
+            Ok(())
+        }
+    }
+
+    fn client(cors: CorsArmor) -> TestClient {
+        let mut app = App::new();
+        app.with_armor(Box::new(cors)).add_module(Box::new(Api));
+        TestClient::new(app)
+    }
+
+    #[test]
+    fn it_works() {}
+
+    #[test]
+    fn preflight() {
+        let client = client(
+            CorsArmor::new()
+                .allow_origin("https://*.example.com")
+                .allow_origin_regex(r"^https://app\d+\.test$")
+                .allow_methods(&[Method::Get, Method::Post])
+                .allow_headers(&["Content-Type"])
+                .allow_credentials(true)
+                .max_age(Duration::from_secs(600)),
+        );
+
+        let res = client
+            .options("/items")
+            .header("Origin", "https://api.example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .send();
+        assert_eq!(res.status(), StatusCode::NoContent);
+        assert_eq!(
+            res.header("Access-Control-Allow-Origin"),
+            Some("https://api.example.com".to_owned())
+        );
+        assert_eq!(
+            res.header("Access-Control-Allow-Methods"),
+            Some("GET, POST".to_owned())
+        );
+        assert_eq!(
+            res.header("Access-Control-Allow-Headers"),
+            Some("content-type".to_owned())
+        );
+        assert_eq!(
+            res.header("Access-Control-Allow-Credentials"),
+            Some("true".to_owned())
+        );
+        assert_eq!(res.header("Access-Control-Max-Age"), Some("600".to_owned()));
+
+        let res = client
+            .options("/items")
+            .header("Origin", "https://app42.test")
+            .header("Access-Control-Request-Method", "GET")
+            .send();
+        assert!(res.header("Access-Control-Allow-Origin").is_some());
+
+        // not allowed: the origin, a method, a header
+        for &(origin, method, headers) in &[
+            ("https://evil.test", "GET", ""),
+            ("https://api.example.com", "DELETE", ""),
+            ("https://api.example.com", "GET", "X-Secret"),
+        ] {
+            let res = client
+                .options("/items")
+                .header("Origin", origin)
+                .header("Access-Control-Request-Method", method)
+                .header("Access-Control-Request-Headers", headers)
+                .send();
+            assert_eq!(res.header("Access-Control-Allow-Origin"), None);
+        }
+
+        // an app answered `OPTIONS`, with no preflight
+        let res = client.options("/items").send();
+        assert_eq!(res.status(), StatusCode::NoContent);
+        assert_eq!(res.header("Allow"), Some("GET, OPTIONS, POST".to_owned()));
+    }
+
+    #[test]
+    fn simple_requests() {
+        let client = client(
+            CorsArmor::new()
+                .allow_any_origin()
+                .expose_headers(&["X-Total"]),
+        );
+
+        let res = client
+            .get("/items")
+            .header("Origin", "https://any.test")
+            .send();
+        assert_eq!(res.text(), "items");
+        assert_eq!(
+            res.header("Access-Control-Allow-Origin"),
+            Some("*".to_owned())
+        );
+        assert_eq!(
+            res.header("Access-Control-Expose-Headers"),
+            Some("X-Total".to_owned())
+        );
+
+        let res = client.get("/items").send();
+        assert_eq!(res.header("Access-Control-Allow-Origin"), None);
+    }
+
+    #[test]
+    fn star_origin() {
+        // `*` is any origin, answered `*` without credentials
+        let res = client(CorsArmor::new().allow_origin("*"))
+            .get("/items")
+            .header("Origin", "https://any.test")
+            .send();
+        assert_eq!(
+            res.header("Access-Control-Allow-Origin"),
+            Some("*".to_owned())
+        );
+        assert_eq!(res.header("Access-Control-Allow-Credentials"), None);
+    }
+}
@@ -0,0 +1,362 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sapper::{Armor, Cookie, Error, Key, Method, Request, Response, Result, SameSite};
+use sapper_body::FormParams;
+use sapper_session::SessionExt;
+
+// the session key the token is kept under, in session mode
+const SESSION_KEY: &str = "_csrf";
+
+/// The csrf token of a request, to put in the forms of the page
+#[derive(Clone, Debug)]
+pub struct CsrfToken {
+    value: String,
+    field: String,
+}
+
+impl CsrfToken {
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// the name of the form field the token is read from
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// the hidden input to put in a form,
+    /// `<input type="hidden" name="csrf_token" value="...">`
+    pub fn hidden_input(&self) -> String {
+        format!(
+            "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+            self.field, self.value
+        )
+    }
+}
+
+/// The ext key of the csrf token
+pub struct CsrfData;
+
+impl Key for CsrfData {
+    type Value = CsrfToken;
+}
+
+// a token issued by this request, to set as the cookie
+struct Issued;
+
+impl Key for Issued {
+    type Value = String;
+}
+
+/// the csrf token of the request, `None` when no `CsrfArmor` runs for it
+pub fn csrf_token(req: &Request) -> Option<&CsrfToken> {
+    req.ext().get::<CsrfData>()
+}
+
+enum Mode {
+    DoubleSubmit,
+    Session,
+}
+
+/// Csrf protection: requests of unsafe methods, not `GET`, `HEAD`,
+/// `OPTIONS` nor `TRACE`, must send the token of the client, in the
+/// `X-CSRF-Token` header or the `csrf_token` form field, or are answered
+/// `403 Forbidden`.
+///
+/// The token is kept in a cookie, double submit, or in the session, which
+/// needs the `SessionArmor` to run before. The double submit cookie is never
+/// signed, even when `CookieKeys` are managed, scripts send its value as it
+/// is in the header. Webhooks and other routes posted by other sites are exempted by
+/// path, a trailing `*` matches any rest.
+///
+/// ```ignore
+/// app.with_armor(Box::new(CsrfArmor::double_submit().exempt("/hooks/*")));
+/// // in a template rendered with the request
+/// <form method="post">{{ csrf_field | safe }} ...</form>
+/// ```
+pub struct CsrfArmor {
+    mode: Mode,
+    cookie_name: String,
+    field: String,
+    header: String,
+    secure: bool,
+    exempt: Vec<String>,
+}
+
+impl CsrfArmor {
+    fn new(mode: Mode) -> CsrfArmor {
+        CsrfArmor {
+            mode,
+            cookie_name: "csrf_token".to_owned(),
+            field: "csrf_token".to_owned(),
+            header: "X-CSRF-Token".to_owned(),
+            secure: false,
+            exempt: Vec::new(),
+        }
+    }
+
+    /// tokens kept in a cookie, which the client must send back
+    pub fn double_submit() -> CsrfArmor {
+        CsrfArmor::new(Mode::DoubleSubmit)
+    }
+
+    /// tokens kept in the session of the client
+    pub fn session() -> CsrfArmor {
+        CsrfArmor::new(Mode::Session)
+    }
+
+    pub fn cookie_name(mut self, name: &str) -> Self {
+        self.cookie_name = name.to_owned();
+        self
+    }
+
+    pub fn field_name(mut self, name: &str) -> Self {
+        self.field = name.to_owned();
+        self
+    }
+
+    pub fn header_name(mut self, name: &str) -> Self {
+        self.header = name.to_owned();
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// do not check the requests to `path`, `/hooks/*` exempts all the
+    /// paths under `/hooks/`
+    pub fn exempt(mut self, path: &str) -> Self {
+        self.exempt.push(path.to_owned());
+        self
+    }
+
+    fn exempted(&self, path: &str) -> bool {
+        self.exempt.iter().any(|exempt| {
+            if exempt.ends_with('*') {
+                path.starts_with(&exempt[..exempt.len() - 1])
+            } else {
+                path == exempt
+            }
+        })
+    }
+
+    // the token of the client, a new one when it has none yet
+    fn token(&self, req: &mut Request) -> Result<String> {
+        match self.mode {
+            Mode::DoubleSubmit => match req.cookies().get(&self.cookie_name) {
+                Some(cookie) if !cookie.value().is_empty() => Ok(cookie.value().to_owned()),
+                _ => {
+                    let token = new_token()?;
+                    req.ext_mut().insert::<Issued>(token.clone());
+                    Ok(token)
+                }
+            },
+            Mode::Session => {
+                let session = req.session()?;
+                match session.get::<String>(SESSION_KEY) {
+                    Some(token) => Ok(token),
+                    None => {
+                        let token = new_token()?;
+                        session.set(SESSION_KEY, &token)?;
+                        Ok(token)
+                    }
+                }
+            }
+        }
+    }
+
+    // the token sent with the request, from the header, or the form
+    fn submitted(&self, req: &mut Request) -> Result<Option<String>> {
+        if let Some(values) = req.headers().get_raw(&self.header) {
+            return Ok(values
+                .first()
+                .map(|value| String::from_utf8_lossy(value).trim().to_owned()));
+        }
+
+        // the body is parsed into the ext, it is left for the handler
+        if req.ext().get::<FormParams>().is_none() {
+            sapper_body::parse(req)?;
+        }
+        Ok(req
+            .ext()
+            .get::<FormParams>()
+            .and_then(|params| params.get(&self.field))
+            .and_then(|values| values.first())
+            .cloned())
+    }
+}
+
+fn safe_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::Get | Method::Head | Method::Options | Method::Trace
+    )
+}
+
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| Error::InternalServerError(format!("csrf token: {}", err)))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+// compared in constant time, not to leak how much of a guess is right
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl Armor for CsrfArmor {
+    fn before(&self, req: &mut Request) -> Result<()> {
+        let token = self.token(req)?;
+
+        if !safe_method(req.method()) && !self.exempted(req.path()) {
+            let valid = match self.submitted(req)? {
+                Some(submitted) => same(submitted.as_bytes(), token.as_bytes()),
+                None => false,
+            };
+            if !valid {
+                return Err(Error::Forbidden);
+            }
+        }
+
+        req.ext_mut().insert::<CsrfData>(CsrfToken {
+            value: token,
+            field: self.field.clone(),
+        });
+        Ok(())
+    }
+
+    fn after(&self, req: &Request, res: &mut Response) -> Result<()> {
+        if let Some(token) = req.ext().get::<Issued>() {
+            // readable by scripts, to send it in the header
+            let cookie = Cookie::new(self.cookie_name.clone(), token.as_str())
+                .path("/")
+                .secure(self.secure)
+                .same_site(SameSite::Lax);
+            res.cookies_mut().add(cookie);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csrf_token, CsrfArmor};
+    use sapper::status::StatusCode;
+    use sapper::{App, Module, Request, Response, Result, Router, TestClient};
+    use sapper_session::{CookieKeys, MemoryStore, SessionArmor};
+
+    struct Forms;
+
+    impl Module for Forms {
+        fn router(&self, router: &mut Router) -> Result<()> {
+            router.get("/form", |req: &mut Request| {
+                let mut res = Response::new();
+                res.write_body(csrf_token(req).unwrap().value().to_owned());
+                Ok(res)
+            });
+            router.post("/save", |req: &mut Request| {
+                let mut res = Response::new();
+                res.write_raw_body(req.body().unwrap_or_default());
+                Ok(res)
+            });
+            router.post("/hooks/github", |_: &mut Request| Ok(Response::new()));
+            Ok(())
+        }
+    }
+
+    fn cookie(set_cookie: &str) -> String {
+        set_cookie.split(';').next().unwrap().to_owned()
+    }
+
+    #[test]
+    fn it_works() {}
+
+    #[test]
+    fn double_submit() {
+        let mut app = App::new();
+        app.with_armor(Box::new(CsrfArmor::double_submit().exempt("/hooks/*")))
+            .add_module(Box::new(Forms));
+        let client = TestClient::new(app);
+
+        let res = client.get("/form").send();
+        let token = res.text();
+        let cookie = cookie(&res.header("Set-Cookie").unwrap());
+        assert_eq!(cookie, format!("csrf_token={}", token));
+
+        let form = format!("name=sapper&csrf_token={}", token);
+        let res = client
+            .post("/save")
+            .header("Cookie", &cookie)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(form.as_str())
+            .send();
+        assert_eq!(res.status(), StatusCode::Ok);
+        // the form the armor parsed is still there for the handler
+        assert_eq!(res.text(), form);
+
+        let res = client
+            .post("/save")
+            .header("Cookie", &cookie)
+            .header("X-CSRF-Token", &token)
+            .send();
+        assert_eq!(res.status(), StatusCode::Ok);
+
+        // no token, a token of another client
+        let res = client.post("/save").header("Cookie", &cookie).send();
+        assert_eq!(res.status(), StatusCode::Forbidden);
+        let res = client.post("/save").header("X-CSRF-Token", &token).send();
+        assert_eq!(res.status(), StatusCode::Forbidden);
+
+        assert_eq!(client.post("/hooks/github").send().status(), StatusCode::Ok);
+    }
+
+    #[test]
+    fn double_submit_with_keys() {
+        let mut app = App::new();
+        app.manage(CookieKeys::new(&[5; 32]))
+            .with_armor(Box::new(CsrfArmor::double_submit()))
+            .add_module(Box::new(Forms));
+        let client = TestClient::new(app);
+
+        // the cookie is not signed, a script sends its value in the header
+        let res = client.get("/form").send();
+        let token = res.text();
+        let cookie = cookie(&res.header("Set-Cookie").unwrap());
+        assert_eq!(cookie, format!("csrf_token={}", token));
+
+        let value = &cookie["csrf_token=".len()..];
+        let res = client
+            .post("/save")
+            .header("Cookie", &cookie)
+            .header("X-CSRF-Token", value)
+            .send();
+        assert_eq!(res.status(), StatusCode::Ok);
+    }
+
+    #[test]
+    fn session_bound() {
+        let mut app = App::new();
+        app.with_armor(Box::new(SessionArmor::new(MemoryStore::new())))
+            .with_armor(Box::new(CsrfArmor::session()))
+            .add_module(Box::new(Forms));
+        let client = TestClient::new(app);
+
+        let res = client.get("/form").send();
+        let token = res.text();
+        let cookie = cookie(&res.header("Set-Cookie").unwrap());
+        assert!(cookie.starts_with("sapper_session="));
+
+        let res = client
+            .post("/save")
+            .header("Cookie", &cookie)
+            .header("X-CSRF-Token", &token)
+            .send();
+        assert_eq!(res.status(), StatusCode::Ok);
+
+        let res = client.post("/save").header("X-CSRF-Token", &token).send();
+        assert_eq!(res.status(), StatusCode::Forbidden);
+    }
+}
@@ -22,11 +22,17 @@ pub fn splog(req: &Request, status: sapper::status::StatusCode) -> Result<()> {
     // info!()
     let timedate = format!("[{}] ", time::now().strftime("%Y-%m-%d %H:%M:%S").unwrap());
     let method = format!("{}", req.method());
-    let (path, query) = req.uri();
+    let (path, query) = (req.path(), req.query());
     let response_time = format!("{} ms", response_time_ms);
     info!(
-        "{} {} {} {:?} -> {} ({})",
-        timedate, method, path, query, status, response_time
+        "{}{} {} {} {:?} -> {} ({})",
+        timedate,
+        req.client_ip(),
+        method,
+        path,
+        query,
+        status,
+        response_time
     );
 
     Ok(())
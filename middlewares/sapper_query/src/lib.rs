@@ -12,14 +12,13 @@ impl Key for QueryParams {
 }
 
 pub fn parse(req: &mut Request) -> Result<()> {
-    let (_, query) = req.uri();
-
-    if query.is_none() {
-        req.ext_mut().insert::<QueryParams>(HashMap::new());
-        return Ok(());
-    }
-
-    let query_string = query.unwrap();
+    let query_string = match req.query() {
+        Some(query) => query.to_owned(),
+        None => {
+            req.ext_mut().insert::<QueryParams>(HashMap::new());
+            return Ok(());
+        }
+    };
 
     let query_iter = form_urlencoded::parse(query_string.as_bytes());
 
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// The level of a flash message, serialized lowercase, `"success"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Debug,
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+/// A one time message for the next request of the session, the
+/// "saved successfully" shown after the redirect of a form post
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flash {
+    pub level: Level,
+    pub message: String,
+}
+
+// the session key the flashes for the next request are kept under
+pub(crate) const FLASH_KEY: &str = "_flash";
@@ -0,0 +1,148 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sapper::Cookie;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// the keys derived from one app secret
+struct Key {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+impl Key {
+    fn derive(secret: &[u8]) -> Key {
+        assert!(
+            secret.len() >= 32,
+            "cookie secret must be at least 32 bytes long"
+        );
+        Key {
+            signing: derive(secret, b"sapper signed cookie"),
+            encryption: derive(secret, b"sapper private cookie"),
+        }
+    }
+
+    // the mac of a cookie binds its name, so values can not be swapped
+    fn mac(&self, name: &str, value: &str) -> HmacSha256 {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.signing).unwrap();
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        let dot = signed.find('.')?;
+        let tag = URL_SAFE_NO_PAD.decode(&signed[..dot]).ok()?;
+        let value = &signed[dot + 1..];
+        self.mac(name, value).verify_slice(&tag).ok()?;
+        Some(value.to_owned())
+    }
+
+    fn decrypt(&self, name: &str, sealed: &str) -> Option<String> {
+        let sealed = URL_SAFE_NO_PAD.decode(sealed).ok()?;
+        if sealed.len() < 12 {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(12);
+        let cipher = Aes256Gcm::new_from_slice(&self.encryption).ok()?;
+        let plain = cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .ok()?;
+        String::from_utf8(plain).ok()
+    }
+}
+
+fn derive(secret: &[u8], label: &[u8]) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(secret).unwrap();
+    mac.update(label);
+    mac.finalize().into_bytes().into()
+}
+
+/// Keys for signed and private cookies, derived from an app secret.
+///
+/// Signed cookies can be read by the client but not forged, private ones
+/// can neither be read nor forged. Manage the keys as app state, then
+/// `session_val` rejects tampered session cookies:
+///
+/// ```ignore
+/// app.manage(CookieKeys::new(secret).rotate(old_secret));
+/// // in a handler
+/// let keys = req.state::<CookieKeys>()?;
+/// res.cookies_mut().add(keys.private(Cookie::new("sid", sid).path("/")));
+/// ```
+pub struct CookieKeys {
+    // the current key first, then the old ones still accepted
+    keys: Vec<Key>,
+}
+
+impl CookieKeys {
+    /// keys derived from `secret`, which must be at least 32 bytes long
+    pub fn new(secret: &[u8]) -> CookieKeys {
+        CookieKeys {
+            keys: vec![Key::derive(secret)],
+        }
+    }
+
+    /// still accept cookies made with `old_secret`, new cookies are made
+    /// with the current secret only
+    pub fn rotate(mut self, old_secret: &[u8]) -> CookieKeys {
+        self.keys.push(Key::derive(old_secret));
+        self
+    }
+
+    /// sign the value of `cookie`
+    pub fn signed(&self, mut cookie: Cookie) -> Cookie {
+        let tag = self.keys[0]
+            .mac(cookie.name(), cookie.value())
+            .finalize()
+            .into_bytes();
+        let value = format!("{}.{}", URL_SAFE_NO_PAD.encode(tag), cookie.value());
+        cookie.set_value(value);
+        cookie
+    }
+
+    /// encrypt the value of `cookie`
+    pub fn private(&self, mut cookie: Cookie) -> Cookie {
+        let cipher = Aes256Gcm::new_from_slice(&self.keys[0].encryption).unwrap();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: cookie.value().as_bytes(),
+                    aad: cookie.name().as_bytes(),
+                },
+            )
+            .expect("cookie encryption failed");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        cookie.set_value(URL_SAFE_NO_PAD.encode(sealed));
+        cookie
+    }
+
+    /// the value of a signed cookie, `None` when it was tampered with
+    pub fn verify(&self, cookie: &Cookie) -> Option<String> {
+        self.keys
+            .iter()
+            .find_map(|key| key.verify(cookie.name(), cookie.value()))
+    }
+
+    /// the value of a private cookie, `None` when it was tampered with
+    pub fn decrypt(&self, cookie: &Cookie) -> Option<String> {
+        self.keys
+            .iter()
+            .find_map(|key| key.decrypt(cookie.name(), cookie.value()))
+    }
+}
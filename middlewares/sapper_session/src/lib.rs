@@ -1,6 +1,14 @@
-use cookie::Cookie as Cookie_M;
-use sapper::header::{Cookie, SetCookie};
-use sapper::{Key, Request, Response, Result};
+use sapper::{Cookie, Key, Request, Response, Result};
+
+mod flash;
+mod keys;
+mod session;
+mod store;
+
+pub use flash::{Flash, Level};
+pub use keys::CookieKeys;
+pub use session::{Session, SessionArmor, SessionData, SessionExt};
+pub use store::{FileStore, MemoryStore, SessionRecord, SessionStore};
 
 pub struct SessionVal;
 impl Key for SessionVal {
@@ -12,39 +20,24 @@ pub fn session_val(req: &mut Request, ckey: Option<&'static str>) -> Result<()>
         return Ok(());
     }
 
-    let mut session_value: Option<String> = None;
-    match req.headers().get::<Cookie>() {
-        Some(cookie_headers) => {
-            //let mut cookie_jar = CookieJar::new();
-            for header in cookie_headers.iter() {
-                let raw_str = match ::std::str::from_utf8(&header.as_bytes()) {
-                    Ok(string) => string,
-                    Err(_) => continue,
-                };
-
-                for cookie_str in raw_str.split(";").map(|s| s.trim()) {
-                    if let Ok(cookie) = Cookie_M::parse(cookie_str) {
-                        if cookie.name() == ckey.unwrap() {
-                            session_value = Some(cookie.value().to_owned());
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-        None => {
-            println!("no cookie in headers");
-        }
-    }
-
-    session_value.and_then(|val| {
+    if let Some(val) = cookie_value(req, ckey.unwrap()) {
         req.ext_mut().insert::<SessionVal>(val);
-        Some(())
-    });
+    }
 
     Ok(())
 }
 
+/// the value of cookie `name`: with `CookieKeys` managed, only a private or
+/// signed cookie is trusted, a tampered one is taken as missing
+pub fn cookie_value(req: &Request, name: &str) -> Option<String> {
+    let cookies = req.cookies();
+    let cookie = cookies.get(name)?;
+    match req.state::<CookieKeys>() {
+        Ok(keys) => keys.decrypt(cookie).or_else(|| keys.verify(cookie)),
+        Err(_) => Some(cookie.value().to_owned()),
+    }
+}
+
 // library function
 pub fn set_cookie(
     res: &mut Response,
@@ -55,27 +48,220 @@ pub fn set_cookie(
     secure: Option<bool>,
     max_age: Option<i64>,
 ) -> Result<()> {
-    let mut cookie = Cookie_M::new(ckey, val);
-    if domain.is_some() {
-        cookie.set_domain(domain.unwrap());
+    let mut cookie = Cookie::new(ckey, val);
+    if let Some(domain) = domain {
+        cookie = cookie.domain(domain);
     }
-    if path.is_some() {
-        cookie.set_path(path.unwrap());
+    if let Some(path) = path {
+        cookie = cookie.path(path);
     }
-    if secure.is_some() {
-        cookie.set_secure(secure.unwrap());
+    if let Some(secure) = secure {
+        cookie = cookie.secure(secure);
     }
-    if max_age.is_some() {
-        cookie.set_max_age(time::Duration::seconds(max_age.unwrap()))
+    if let Some(max_age) = max_age {
+        cookie = cookie.max_age(max_age);
     }
 
-    res.headers_mut().set(SetCookie(vec![cookie.to_string()]));
+    // added to the response cookies, so cookies set earlier are kept
+    res.cookies_mut().add(cookie);
+
+    Ok(())
+}
+
+/// set `cookie` on the response, signed when `CookieKeys` is managed, so
+/// `cookie_value` and `session_val` read it back
+pub fn set_signed_cookie(req: &Request, res: &mut Response, cookie: Cookie) -> Result<()> {
+    let cookie = match req.state::<CookieKeys>() {
+        Ok(keys) => keys.signed(cookie),
+        Err(_) => cookie,
+    };
+    res.cookies_mut().add(cookie);
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{
+        session_val, set_cookie, set_signed_cookie, CookieKeys, Level, MemoryStore, SessionArmor,
+        SessionExt, SessionVal,
+    };
+    use sapper::{App, Cookie, Module, Request, Response, Result, Router, TestClient};
+
+    struct Counter;
+
+    impl Module for Counter {
+        fn router(&self, router: &mut Router) -> Result<()> {
+            router.get("/count", |req: &mut Request| {
+                let session = req.session()?;
+                let count = session.get::<u32>("count").unwrap_or(0) + 1;
+                session.set("count", count)?;
+                let mut res = Response::new();
+                res.write_body(count.to_string());
+                Ok(res)
+            });
+            router.get("/login", |req: &mut Request| {
+                req.session()?.regenerate();
+                Ok(Response::new())
+            });
+            router.post("/save", |req: &mut Request| {
+                let session = req.session()?;
+                session.flash(Level::Success, "saved");
+                session.flash(Level::Warning, "almost full");
+                Ok(Response::new())
+            });
+            router.get("/show", |req: &mut Request| {
+                let flashes: Vec<String> = req
+                    .session()?
+                    .flashes()
+                    .iter()
+                    .map(|flash| format!("{:?}: {}", flash.level, flash.message))
+                    .collect();
+                let mut res = Response::new();
+                res.write_body(flashes.join(", "));
+                Ok(res)
+            });
+            router.get("/logout", |req: &mut Request| {
+                req.session()?.destroy();
+                Ok(Response::new())
+            });
+            router.get("/remember", |req: &mut Request| {
+                let mut res = Response::new();
+                set_signed_cookie(req, &mut res, Cookie::new("uid", "7").path("/"))?;
+                Ok(res)
+            });
+            router.get("/plain", |_req: &mut Request| {
+                let mut res = Response::new();
+                set_cookie(
+                    &mut res,
+                    "uid".to_owned(),
+                    "7".to_owned(),
+                    None,
+                    Some("/".to_owned()),
+                    None,
+                    None,
+                )?;
+                Ok(res)
+            });
+            router.get("/whoami", |req: &mut Request| {
+                session_val(req, Some("uid"))?;
+                let mut res = Response::new();
+                res.write_body(req.ext().get::<SessionVal>().cloned().unwrap_or_default());
+                Ok(res)
+            });
+            Ok(())
+        }
+    }
+
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn signed_and_private_cookies() {
+        let old = CookieKeys::new(&[1; 32]);
+        let keys = CookieKeys::new(&[2; 32]).rotate(&[1; 32]);
+
+        let signed = keys.signed(Cookie::new("sid", "42"));
+        assert_eq!(keys.verify(&signed), Some("42".to_owned()));
+        let mut forged = signed.clone();
+        forged.set_value(signed.value().replace("42", "43"));
+        assert_eq!(keys.verify(&forged), None);
+        // bound to the cookie name
+        let renamed = Cookie::new("uid", signed.value());
+        assert_eq!(keys.verify(&renamed), None);
+
+        let private = keys.private(Cookie::new("sid", "session-42"));
+        assert!(!private.value().contains("session"));
+        assert_eq!(keys.decrypt(&private), Some("session-42".to_owned()));
+        assert_eq!(keys.verify(&private), None);
+
+        // cookies of the old secret are still accepted, not the other way
+        let rotated = old.private(Cookie::new("sid", "7"));
+        assert_eq!(keys.decrypt(&rotated), Some("7".to_owned()));
+        assert_eq!(old.decrypt(&private), None);
+    }
+
+    #[test]
+    fn sessions() {
+        let mut app = App::new();
+        app.manage(CookieKeys::new(&[3; 32]))
+            .with_armor(Box::new(SessionArmor::new(MemoryStore::new())))
+            .add_module(Box::new(Counter));
+        let client = TestClient::new(app);
+
+        let res = client.get("/count").send();
+        assert_eq!(res.text(), "1");
+        let set_cookie = res.header("Set-Cookie").unwrap();
+        assert!(set_cookie.contains("HttpOnly"));
+        let cookie = set_cookie.split(';').next().unwrap().to_owned();
+
+        let res = client.get("/count").header("Cookie", &cookie).send();
+        assert_eq!(res.text(), "2");
+        // the cookie is set once
+        assert_eq!(res.header("Set-Cookie"), None);
+
+        // a forged id starts a new session
+        let forged = format!("sapper_session=x{}", &cookie["sapper_session=".len()..]);
+        let res = client.get("/count").header("Cookie", &forged).send();
+        assert_eq!(res.text(), "1");
+
+        // the data moves to the new id, the old one is gone
+        let res = client.get("/login").header("Cookie", &cookie).send();
+        let new_cookie = res.header("Set-Cookie").unwrap();
+        let new_cookie = new_cookie.split(';').next().unwrap().to_owned();
+        assert_ne!(new_cookie, cookie);
+        let res = client.get("/count").header("Cookie", &cookie).send();
+        assert_eq!(res.text(), "1");
+        let res = client.get("/count").header("Cookie", &new_cookie).send();
+        assert_eq!(res.text(), "3");
+
+        let res = client.get("/logout").header("Cookie", &new_cookie).send();
+        assert!(res.header("Set-Cookie").unwrap().contains("Max-Age=0"));
+        let res = client.get("/count").header("Cookie", &new_cookie).send();
+        assert_eq!(res.text(), "1");
+    }
+
+    #[test]
+    fn set_cookie_signed() {
+        let mut app = App::new();
+        app.manage(CookieKeys::new(&[4; 32]))
+            .add_module(Box::new(Counter));
+        let client = TestClient::new(app);
+
+        let res = client.get("/remember").send();
+        let cookie = res.header("Set-Cookie").unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_owned();
+        assert_ne!(cookie, "uid=7");
+
+        let res = client.get("/whoami").header("Cookie", &cookie).send();
+        assert_eq!(res.text(), "7");
+        // a plain or forged value is not trusted
+        let res = client.get("/whoami").header("Cookie", "uid=7").send();
+        assert_eq!(res.text(), "");
+        let forged = cookie.replace(".7", ".8");
+        let res = client.get("/whoami").header("Cookie", &forged).send();
+        assert_eq!(res.text(), "");
+        // set_cookie leaves the value as it is
+        let res = client.get("/plain").send();
+        let cookie = res.header("Set-Cookie").unwrap();
+        assert!(cookie.starts_with("uid=7;"));
+    }
+
+    #[test]
+    fn flashes() {
+        let mut app = App::new();
+        app.with_armor(Box::new(SessionArmor::new(MemoryStore::new())))
+            .add_module(Box::new(Counter));
+        let client = TestClient::new(app);
+
+        let res = client.post("/save").send();
+        let cookie = res.header("Set-Cookie").unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_owned();
+
+        let res = client.get("/show").header("Cookie", &cookie).send();
+        assert_eq!(res.text(), "Success: saved, Warning: almost full");
+        // shown once
+        let res = client.get("/show").header("Cookie", &cookie).send();
+        assert_eq!(res.text(), "");
+    }
 }
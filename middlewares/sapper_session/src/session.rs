@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sapper::{Armor, Cookie, Error, Key, Request, Response, Result, SameSite};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::flash::{Flash, Level, FLASH_KEY};
+use crate::store::{now, SessionRecord, SessionStore};
+use crate::{cookie_value, CookieKeys};
+
+/// The session of a request, loaded by `SessionArmor`
+#[derive(Clone, Debug)]
+pub struct Session {
+    id: String,
+    record: SessionRecord,
+    is_new: bool,
+    // the id to drop from the store, after `regenerate`
+    old_id: Option<String>,
+    destroyed: bool,
+    // the flashes of the previous request, consumed as the session loads
+    flashes: Vec<Flash>,
+}
+
+impl Session {
+    fn new() -> Session {
+        let now = now();
+        Session {
+            id: new_id(),
+            record: SessionRecord {
+                data: HashMap::new(),
+                created: now,
+                accessed: now,
+            },
+            is_new: true,
+            old_id: None,
+            destroyed: false,
+            flashes: Vec::new(),
+        }
+    }
+
+    fn load(id: String, mut record: SessionRecord) -> Session {
+        let flashes = record
+            .data
+            .remove(FLASH_KEY)
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default();
+        Session {
+            id,
+            record,
+            is_new: false,
+            old_id: None,
+            destroyed: false,
+            flashes,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// whether the session was started by this request
+    pub fn is_new(&self) -> bool {
+        self.is_new
+    }
+
+    /// the value of `key`, `None` when missing or not a `T`
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.record
+            .data
+            .get(key)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+
+    pub fn set<T: Serialize>(&mut self, key: &str, value: T) -> Result<()> {
+        let value = serde_json::to_value(value)
+            .map_err(|err| Error::InternalServerError(err.to_string()))?;
+        self.record.data.insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.record.data.remove(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.record.data.clear();
+    }
+
+    /// move the session to a new id, do it when the user logs in,
+    /// so an id planted before can not be used to ride the session
+    pub fn regenerate(&mut self) {
+        if !self.is_new && self.old_id.is_none() {
+            self.old_id = Some(self.id.clone());
+        }
+        self.id = new_id();
+        self.is_new = true;
+    }
+
+    /// add a flash message, shown by the next request of the session
+    pub fn flash<S: Into<String>>(&mut self, level: Level, message: S) {
+        let mut pending: Vec<Flash> = self.get(FLASH_KEY).unwrap_or_default();
+        pending.push(Flash {
+            level,
+            message: message.into(),
+        });
+        // a vec of plain structs always serializes
+        let _ = self.set(FLASH_KEY, pending);
+    }
+
+    /// the flash messages added by the previous request, they are gone
+    /// for the requests after this one
+    pub fn flashes(&self) -> &[Flash] {
+        &self.flashes
+    }
+
+    /// end the session, its data is dropped and its cookie removed
+    pub fn destroy(&mut self) {
+        self.destroyed = true;
+        self.record.data.clear();
+    }
+}
+
+fn new_id() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The ext key of the session
+pub struct SessionData;
+
+impl Key for SessionData {
+    type Value = Session;
+}
+
+/// Typed session access on requests
+pub trait SessionExt {
+    /// the session of the request, an error when no `SessionArmor` runs
+    /// for it
+    fn session(&mut self) -> Result<&mut Session>;
+}
+
+impl SessionExt for Request {
+    fn session(&mut self) -> Result<&mut Session> {
+        self.ext_mut().get_mut::<SessionData>().ok_or_else(|| {
+            Error::InternalServerError("no session, `SessionArmor` is not in use".to_owned())
+        })
+    }
+}
+
+/// Sessions kept in a `SessionStore` and tracked by a cookie.
+///
+/// The session is loaded before the handler, and saved after it, with its
+/// cookie set when it is new. Sessions expire when unused for the idle
+/// timeout, and in any case after the absolute timeout. When `CookieKeys`
+/// are managed, the cookie is signed.
+///
+/// ```ignore
+/// app.with_armor(Box::new(
+///     SessionArmor::new(MemoryStore::new()).idle_timeout(Duration::from_secs(600)),
+/// ));
+/// // in a handler
+/// req.session()?.set("user_id", 42)?;
+/// ```
+pub struct SessionArmor {
+    store: Arc<dyn SessionStore>,
+    cookie_name: String,
+    cookie_path: String,
+    secure: bool,
+    same_site: SameSite,
+    idle_timeout: Duration,
+    absolute_timeout: Duration,
+}
+
+impl SessionArmor {
+    /// sessions in `store`, idle timeout 30 minutes, absolute timeout
+    /// 12 hours, the cookie is `sapper_session`, http only and lax
+    pub fn new<S: SessionStore + 'static>(store: S) -> SessionArmor {
+        SessionArmor {
+            store: Arc::new(store),
+            cookie_name: "sapper_session".to_owned(),
+            cookie_path: "/".to_owned(),
+            secure: false,
+            same_site: SameSite::Lax,
+            idle_timeout: Duration::from_secs(30 * 60),
+            absolute_timeout: Duration::from_secs(12 * 60 * 60),
+        }
+    }
+
+    pub fn cookie_name(mut self, name: &str) -> Self {
+        self.cookie_name = name.to_owned();
+        self
+    }
+
+    pub fn cookie_path(mut self, path: &str) -> Self {
+        self.cookie_path = path.to_owned();
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn absolute_timeout(mut self, timeout: Duration) -> Self {
+        self.absolute_timeout = timeout;
+        self
+    }
+
+    fn cookie(&self, value: &str) -> Cookie {
+        Cookie::new(self.cookie_name.clone(), value)
+            .path(self.cookie_path.clone())
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+    }
+
+    fn expired(&self, record: &SessionRecord, now: u64) -> bool {
+        now.saturating_sub(record.accessed) > self.idle_timeout.as_secs()
+            || now.saturating_sub(record.created) > self.absolute_timeout.as_secs()
+    }
+
+    fn load(&self, req: &Request) -> Result<Session> {
+        let id = match cookie_value(req, &self.cookie_name) {
+            Some(id) => id,
+            None => return Ok(Session::new()),
+        };
+
+        match self.store.load(&id)? {
+            Some(ref record) if self.expired(record, now()) => {
+                self.store.destroy(&id)?;
+                Ok(Session::new())
+            }
+            Some(record) => Ok(Session::load(id, record)),
+            None => Ok(Session::new()),
+        }
+    }
+}
+
+impl Armor for SessionArmor {
+    fn before(&self, req: &mut Request) -> Result<()> {
+        let session = self.load(req)?;
+        req.ext_mut().insert::<SessionData>(session);
+        Ok(())
+    }
+
+    fn after(&self, req: &Request, res: &mut Response) -> Result<()> {
+        let session = match req.ext().get::<SessionData>() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        if let Some(ref old_id) = session.old_id {
+            self.store.destroy(old_id)?;
+        }
+
+        if session.destroyed {
+            if !session.is_new || session.old_id.is_some() {
+                self.store.destroy(&session.id)?;
+                res.cookies_mut().remove(self.cookie(""));
+            }
+            return Ok(());
+        }
+
+        // a new session is kept only once it has some data
+        if session.is_new && session.record.data.is_empty() {
+            return Ok(());
+        }
+
+        let mut record = session.record.clone();
+        record.accessed = now();
+        let ttl = self.idle_timeout.min(Duration::from_secs(
+            (record.created + self.absolute_timeout.as_secs()).saturating_sub(record.accessed),
+        ));
+        self.store.save(&session.id, &record, ttl)?;
+
+        if session.is_new {
+            let cookie = self.cookie(&session.id);
+            let cookie = match req.state::<CookieKeys>() {
+                Ok(keys) => keys.signed(cookie),
+                Err(_) => cookie,
+            };
+            res.cookies_mut().add(cookie);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sapper::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The stored data of a session
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub data: HashMap<String, Value>,
+    /// unix seconds the session was created at
+    pub created: u64,
+    /// unix seconds the session was last used at
+    pub accessed: u64,
+}
+
+/// A backend to keep sessions in, records expire `ttl` after they are saved
+pub trait SessionStore: Send + Sync {
+    fn load(&self, id: &str) -> Result<Option<SessionRecord>>;
+    fn save(&self, id: &str, record: &SessionRecord, ttl: Duration) -> Result<()>;
+    fn destroy(&self, id: &str) -> Result<()>;
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn store_error<E: ToString>(err: E) -> Error {
+    Error::InternalServerError(format!("session store: {}", err.to_string()))
+}
+
+/// A session store in memory, expired records are evicted as the store
+/// is used, or by `evict_expired`
+#[derive(Default)]
+pub struct MemoryStore {
+    records: Mutex<HashMap<String, (SessionRecord, u64)>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// drop all the expired records
+    pub fn evict_expired(&self) {
+        let now = now();
+        if let Ok(mut records) = self.records.lock() {
+            records.retain(|_, &mut (_, expires)| expires > now);
+        }
+    }
+
+    /// the number of records, expired ones included
+    pub fn len(&self) -> usize {
+        self.records
+            .lock()
+            .map(|records| records.len())
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Result<Option<SessionRecord>> {
+        let mut records = self.records.lock().map_err(store_error)?;
+        match records.get(id) {
+            Some(&(ref record, expires)) if expires > now() => Ok(Some(record.clone())),
+            Some(_) => {
+                records.remove(id);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord, ttl: Duration) -> Result<()> {
+        let now = now();
+        let mut records = self.records.lock().map_err(store_error)?;
+        // new sessions are a good time to drop the expired ones
+        if !records.contains_key(id) {
+            records.retain(|_, &mut (_, expires)| expires > now);
+        }
+        records.insert(id.to_owned(), (record.clone(), now + ttl.as_secs()));
+        Ok(())
+    }
+
+    fn destroy(&self, id: &str) -> Result<()> {
+        self.records.lock().map_err(store_error)?.remove(id);
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileRecord {
+    record: SessionRecord,
+    expires: u64,
+}
+
+/// A session store of one json file per session in a directory,
+/// expired files are removed when loaded, or by `evict_expired`
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// a store in `dir`, which is created when missing
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<FileStore> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(store_error)?;
+        Ok(FileStore { dir })
+    }
+
+    // the file of session `id`, ids other than the generated ones are refused
+    fn path(&self, id: &str) -> Result<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(store_error(format!("invalid session id `{}`", id)));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    /// remove all the expired session files
+    pub fn evict_expired(&self) -> Result<()> {
+        let now = now();
+        for entry in fs::read_dir(&self.dir).map_err(store_error)? {
+            let path = entry.map_err(store_error)?.path();
+            let expired = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<FileRecord>(&bytes).ok())
+                .map(|file| file.expires <= now)
+                .unwrap_or(false);
+            if expired {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self, id: &str) -> Result<Option<SessionRecord>> {
+        let path = match self.path(id) {
+            Ok(path) => path,
+            // a forged id has no session
+            Err(_) => return Ok(None),
+        };
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(store_error(err)),
+        };
+
+        match serde_json::from_slice::<FileRecord>(&bytes) {
+            Ok(file) if file.expires > now() => Ok(Some(file.record)),
+            _ => {
+                let _ = fs::remove_file(&path);
+                Ok(None)
+            }
+        }
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord, ttl: Duration) -> Result<()> {
+        let path = self.path(id)?;
+        let file = FileRecord {
+            record: record.clone(),
+            expires: now() + ttl.as_secs(),
+        };
+        let bytes = serde_json::to_vec(&file).map_err(store_error)?;
+
+        // written aside then renamed, so a load never sees half a file
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes).map_err(store_error)?;
+        fs::rename(&tmp, &path).map_err(store_error)
+    }
+
+    fn destroy(&self, id: &str) -> Result<()> {
+        let path = match self.path(id) {
+            Ok(path) => path,
+            Err(_) => return Ok(()),
+        };
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(store_error(err)),
+        }
+    }
+}
@@ -1,29 +1,55 @@
+use std::any::{type_name, Any};
 use std::clone::Clone;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
+use std::net::SocketAddr;
 use std::path::Path;
 use std::str;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 
-use hyper::server::{Handler, Request, Response, Server};
+use armor::{BeforeArmor, ModuleArmor};
+use hyper::server::{Handler, Listening, Request, Response, Server};
 use hyper::status::StatusCode;
+use middleware::MiddlewareStack;
 use mime_types::Types as MimeTypes;
+use proxy::TrustedProxies;
+use state::StateMap;
 
+pub use armor::{SapperArmor, SapperArmorType};
+pub use cookie::{Cookie, CookieJar, SameSite};
+pub use guard::SapperGuard;
 pub use handler::SapperHandler;
+pub use headers::HeaderMap;
+pub use http::{Method, Version};
 pub use hyper::client::Client;
 pub use hyper::header;
-pub use hyper::header::Headers;
 pub use hyper::mime;
-pub use request::SapperRequest;
+pub use middleware::{SapperMiddleware, SapperMiddlewareType};
+pub use request::{SapperRequest, SapperRequestBuilder};
 pub use response::SapperResponse;
 pub use router::SapperRouter;
 pub use router_m::Router;
 pub use typemap::Key;
+pub use uri::Uri;
 
 /// Path parameter type
 #[derive(Clone)]
 pub struct PathParams;
 
+/// Mount prefix type, the path prefix the handling module was mounted under,
+/// with the prefixes of the groups and nested modules of the route
+#[derive(Clone)]
+pub struct MountPrefix;
+
+impl Key for MountPrefix {
+    type Value = String;
+}
+
+/// Host parameter type
+#[derive(Clone)]
+pub struct HostParams;
+
 /// Re-export Status Codes
 pub mod status {
     pub use hyper::status::StatusCode;
@@ -39,6 +65,9 @@ pub enum Error {
     NotFound,
     Unauthorized,                // 401
     Forbidden,                   // 403
+    NotAcceptable,               // 406
+    PayloadTooLarge,             // 413
+    UnsupportedMediaType,        // 415
     Break(String),               // 400
     InternalServerError(String), // 500
     Found(String),               // 301
@@ -52,7 +81,7 @@ pub enum Error {
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 /// Sapper module trait
-/// 3 methods: before, after, router
+/// main methods: before, after, router
 pub trait SapperModule: Sync + Send {
     /// module before filter, will be executed before handler
     fn before(&self, req: &mut SapperRequest) -> Result<()> {
@@ -64,18 +93,42 @@ pub trait SapperModule: Sync + Send {
         Ok(())
     }
 
+    /// whether the module after filter is also executed when the handler
+    /// fails, on the response the error is answered with
+    fn after_on_error(&self) -> bool {
+        true
+    }
+
     /// module router method, used to write router collection of this module here
     fn router(&self, &mut SapperRouter) -> Result<()>;
-}
 
-/// Sapper armor trait, used to place global before and after middlewares
-pub trait SapperArmor {
-    fn before(&self, &mut SapperRequest) -> Result<()>;
-    fn after(&self, &SapperRequest, &mut SapperResponse) -> Result<()>;
+    /// executed once when the app starts, after it is configured and built,
+    /// modules are started in the order added, nested ones after their parent
+    fn on_start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// executed once when the app shuts down, in the reverse order of `on_start`
+    fn on_shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// health check of the module, aggregated by the app health endpoint
+    fn health(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// name of the module, labels its failures at the app health endpoint,
+    /// the type name by default
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
 }
 
 type GlobalInitClosure = Box<Fn(&mut SapperRequest) -> Result<()> + 'static + Send + Sync>;
-type SapperArmorType = Box<SapperArmor + 'static + Send + Sync>;
+
+// the default largest request body, 10 MiB
+const DEFAULT_BODY_LIMIT: usize = 10 * 1024 * 1024;
 
 /// Sapper app struct
 pub struct SapperApp {
@@ -83,16 +136,40 @@ pub struct SapperApp {
     pub address: String,
     // listen port
     pub port: u32,
-    // for app entry, global middeware
-    pub armor: Option<Arc<SapperArmorType>>,
-    // routers actually use to recognize
+    // for app entry, global armors and middewares, stacked in the order added
+    pub middlewares: MiddlewareStack,
+    // modules with the prefix they are mounted under, in the order added
+    pub modules: Vec<(String, Arc<Box<SapperModule>>)>,
+    // routers actually use to recognize, built from the modules when the app starts
     pub routers: Router,
+    // the app state, init closure and global middlewares, built with the
+    // routers, wrapping the routing of each request
+    stack: MiddlewareStack,
+    // whether the routers are built, later configuration needs another build
+    built: bool,
+    // routers and stack built on the first request, when the app is served
+    // unbuilt
+    lazy_routers: OnceLock<(Router, MiddlewareStack)>,
+    // all modules including the nested ones, in the order to start them
+    lifecycle: Vec<Arc<Box<SapperModule>>>,
+    // path of the health endpoint
+    health_path: Option<String>,
     // do simple static file service
     pub static_file_service: bool,
+    // the directory the static files are served from
+    static_dir: String,
     // if need init something, put them here
     pub init_closure: Option<Arc<GlobalInitClosure>>,
+    // typed states shared by all requests
+    pub state: Arc<StateMap>,
     // 404 not found page
     pub not_found: Option<String>,
+    // proxies trusted to forward the client address, scheme and host
+    proxies: Arc<TrustedProxies>,
+    // the largest request body, in bytes, once decoded
+    body_limit: usize,
+    // set when the server shuts down, the requests still coming are refused
+    shutdown: Arc<AtomicBool>,
 }
 
 impl SapperApp {
@@ -100,11 +177,22 @@ impl SapperApp {
         SapperApp {
             address: String::new(),
             port: 0,
-            armor: None,
+            middlewares: MiddlewareStack::new(),
+            modules: Vec::new(),
             routers: Router::new(),
+            stack: MiddlewareStack::new(),
+            built: false,
+            lazy_routers: OnceLock::new(),
+            lifecycle: Vec::new(),
+            health_path: None,
             static_file_service: true,
+            static_dir: "static".to_owned(),
             init_closure: None,
+            state: Arc::new(StateMap::new()),
             not_found: None,
+            proxies: Arc::new(TrustedProxies::new()),
+            body_limit: DEFAULT_BODY_LIMIT,
+            shutdown: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -126,18 +214,69 @@ impl SapperApp {
         self
     }
 
-    // with global middleware armor
+    // the directory to serve the static files from, `static` by default
+    pub fn static_dir(&mut self, dir: &str) -> &mut Self {
+        self.static_dir = dir.trim_end_matches('/').to_owned();
+        self
+    }
+
+    // with global middleware armor, armors stack in the order added:
+    // `before` are executed in that order, `after` in reverse
     pub fn with_armor(&mut self, w: SapperArmorType) -> &mut Self {
-        self.armor = Some(Arc::new(w));
+        self.warn_if_built("with_armor");
+        self.middlewares.push_armor(w);
+        self
+    }
+
+    // with global middleware wrapping the handler call, stacked with the armors
+    // in the order added
+    pub fn with_middleware(&mut self, m: SapperMiddlewareType) -> &mut Self {
+        self.warn_if_built("with_middleware");
+        self.middlewares.push(m);
         self
     }
 
     // init something, usually in global scope
     pub fn init_global(&mut self, clos: GlobalInitClosure) -> &mut Self {
+        self.warn_if_built("init_global");
         self.init_closure = Some(Arc::new(clos));
         self
     }
 
+    // register a value shared by all requests, handlers get it by its type
+    // with `req.state::<T>()`
+    pub fn manage<T: Any + Send + Sync>(&mut self, value: T) -> &mut Self {
+        self.warn_if_built("manage");
+        Arc::make_mut(&mut self.state).insert(value);
+        self
+    }
+
+    // serve the aggregated health of all modules at `path`:
+    // 200 when all are healthy, otherwise 503 listing the failures
+    pub fn health_endpoint(&mut self, path: &str) -> &mut Self {
+        self.warn_if_built("health_endpoint");
+        self.health_path = Some(path.to_owned());
+        self
+    }
+
+    // trust the forwarding headers, `Forwarded`, `X-Forwarded-For` and
+    // the like, from the proxies in `cidrs`, such as `10.0.0.0/8`
+    pub fn trust_proxies(&mut self, cidrs: &[&str]) -> &mut Self {
+        let proxies = Arc::make_mut(&mut self.proxies);
+        for cidr in cidrs.iter() {
+            proxies.push(cidr.parse().unwrap_or_else(|err| panic!("{}", err)));
+        }
+        self
+    }
+
+    // the largest request body accepted, in bytes, larger ones are answered
+    // `413 Payload Too Large`; the limit applies to bodies once decoded
+    // from their `Content-Encoding` too
+    pub fn max_body_size(&mut self, bytes: usize) -> &mut Self {
+        self.body_limit = bytes;
+        self
+    }
+
     // define 404 not found page here
     pub fn not_found_page(&mut self, page: String) -> &mut Self {
         self.not_found = Some(page);
@@ -146,168 +285,391 @@ impl SapperApp {
 
     // add routers of one module to global routers
     pub fn add_module(&mut self, sm: Box<SapperModule>) -> &mut Self {
-        let mut router = SapperRouter::new();
-        // get the sm router
-        sm.router(&mut router).unwrap();
-        let sm = Arc::new(sm);
-
-        for (method, handler_vec) in router.into_router() {
-            // add to wrapped router
-            for &(glob, ref handler) in handler_vec.iter() {
-                let method = method.clone();
-                let glob = glob.clone();
-                let handler = handler.clone();
-                let sm = sm.clone();
-                let armor = self.armor.clone();
-                let init_closure = self.init_closure.clone();
-
-                self.routers.route(
-                    method,
-                    glob,
-                    Arc::new(Box::new(
-                        move |req: &mut SapperRequest| -> Result<SapperResponse> {
-                            if let Some(ref c) = init_closure {
-                                c(req)?;
-                            }
-                            if let Some(ref armor) = armor {
-                                armor.before(req)?;
-                            }
-                            sm.before(req)?;
-                            let mut response: SapperResponse = handler.handle(req)?;
-                            sm.after(req, &mut response)?;
-                            if let Some(ref armor) = armor {
-                                armor.after(req, &mut response)?;
-                            }
-                            Ok(response)
-                        },
-                    )),
-                );
-            }
-        }
+        self.mount("", sm)
+    }
 
+    // add routers of one module to global routers, each glob prefixed by `prefix`
+    pub fn mount(&mut self, prefix: &str, sm: Box<SapperModule>) -> &mut Self {
+        self.warn_if_built("mount");
+        let prefix = prefix.trim_end_matches('/').to_owned();
+        self.modules.push((prefix, Arc::new(sm)));
         self
     }
 
-    // run http server
-    pub fn run_http(self) {
-        let addr = self.address.clone() + ":" + &self.port.to_string();
-        //let self_box = Arc::new(Box::new(self));
+    // build the routers of all modules, with the middleware chain resolved,
+    // so the configuration order of the app doesn't matter, run_http does
+    // it, an app served by hyper directly builds them on the first request
+    pub fn build(&mut self) -> &mut Self {
+        let (routers, lifecycle) = self.build_routers();
+        self.routers = routers;
+        self.lifecycle = lifecycle;
+        self.stack = self.build_stack();
+        self.built = true;
+        self
+    }
 
-        Server::http(&addr[..]).unwrap().handle(self).unwrap();
+    // from outer to inner: the app state, the init closure, the global
+    // middlewares, wrapping the routing of all requests, the ones not
+    // routed too
+    fn build_stack(&self) -> MiddlewareStack {
+        let mut stack = MiddlewareStack::new();
+        let state = self.state.clone();
+        stack.push_armor(Box::new(BeforeArmor(move |req: &mut SapperRequest| {
+            req.add_state(state.clone());
+            Ok(())
+        })));
+        if let Some(ref c) = self.init_closure {
+            let c = c.clone();
+            stack.push_armor(Box::new(BeforeArmor(move |req: &mut SapperRequest| c(req))));
+        }
+        stack.extend(&self.middlewares);
+        stack
     }
-}
 
-impl Handler for SapperApp {
-    /// do actual handling for a request
-    fn handle(&self, req: Request, mut res: Response) {
-        let mut sreq = SapperRequest::new(Box::new(req));
-        let (path, query) = sreq.uri();
-
-        // pass req to routers, execute matched biz handler
-        let response_w = self.routers.handle_method(&mut sreq, &path);
-        match response_w {
-            Ok(sres) => {
-                *res.status_mut() = sres.status();
-                match sres.body() {
-                    &Some(ref vec) => {
-                        for header in sres.headers().iter() {
-                            res.headers_mut().set_raw(
-                                header.name().to_owned(),
-                                vec![header.value_string().as_bytes().to_vec()],
-                            );
-                        }
-                        return res.send(&vec[..]).unwrap();
-                    }
-                    &None => {
-                        return res.send(&"".as_bytes()).unwrap();
-                    }
-                }
+    // the routers of all modules, and all modules in the order to start them
+    fn build_routers(&self) -> (Router, Vec<Arc<Box<SapperModule>>>) {
+        let mut routers = Router::new();
+        let mut lifecycle = Vec::new();
+
+        // the methods routed for each glob, to answer the `OPTIONS`
+        // requests of globs without an `OPTIONS` route of their own
+        let mut allowed: Vec<(Option<String>, String, Vec<Method>, MiddlewareStack)> = Vec::new();
+
+        // the modules checked by the health endpoint, labelled by the
+        // prefix they are mounted at
+        let mut health = Vec::new();
+
+        for &(ref prefix, ref sm) in self.modules.iter() {
+            let mut router = SapperRouter::new();
+            // get the sm router
+            sm.router(&mut router).unwrap();
+            let nested = router.take_nested();
+            let mount = if prefix.is_empty() { "/" } else { &prefix[..] };
+            for module in Some(sm).into_iter().chain(nested.iter()) {
+                health.push((mount.to_owned(), module.clone()));
             }
-            Err(Error::NotFound) => {
-                if self.static_file_service {
-                    match simple_file_get(&path) {
-                        Ok((file_u8vec, file_mime)) => {
-                            res.headers_mut()
-                                .set_raw("Content-Type", vec![file_mime.as_bytes().to_vec()]);
-                            return res.send(&file_u8vec[..]).unwrap();
-                        }
-                        Err(_) => {
-                            *res.status_mut() = StatusCode::NotFound;
-                            return res
-                                .send(
-                                    self.not_found
-                                        .to_owned()
-                                        .unwrap_or(String::from("404 Not Found"))
-                                        .as_bytes(),
-                                )
-                                .unwrap();
+            lifecycle.push(sm.clone());
+            lifecycle.extend(nested);
+
+            // from outer to inner: mount prefix, module, inside the global
+            // middlewares
+            let mut stack = MiddlewareStack::new();
+            let mount_prefix = prefix.clone();
+            stack.push_armor(Box::new(BeforeArmor(move |req: &mut SapperRequest| {
+                req.ext_mut().insert::<MountPrefix>(mount_prefix.clone());
+                Ok(())
+            })));
+            // the answers to `OPTIONS` are the app's, they skip the module,
+            // which may be one of several routing the glob
+            let app_stack = stack.clone();
+            stack.push_armor(Box::new(ModuleArmor(sm.clone())));
+
+            for (method, endpoints) in router.into_router() {
+                // add to wrapped router
+                for endpoint in endpoints {
+                    // the root of a module mounted at a prefix is routed at
+                    // the prefix itself too, `/api` as well as `/api/`
+                    let mut globs = vec![prefix.clone() + &endpoint.glob];
+                    if !prefix.is_empty() && endpoint.glob == "/" {
+                        globs.push(prefix.clone());
+                    }
+                    for glob in globs {
+                        match allowed
+                            .iter_mut()
+                            .find(|entry| entry.0 == endpoint.host && entry.1 == glob)
+                        {
+                            Some(entry) => {
+                                if !entry.2.contains(&method) {
+                                    entry.2.push(method.clone());
+                                }
+                            }
+                            None => allowed.push((
+                                endpoint.host.clone(),
+                                glob.clone(),
+                                vec![method.clone()],
+                                app_stack.clone(),
+                            )),
                         }
+
+                        routers.route_with(
+                            endpoint.host.clone(),
+                            method.clone(),
+                            glob,
+                            endpoint.guards.clone(),
+                            stack.wrap(endpoint.handler.clone()),
+                        );
                     }
                 }
-
-                // return 404 NotFound now
-                *res.status_mut() = StatusCode::NotFound;
-                return res
-                    .send(
-                        self.not_found
-                            .to_owned()
-                            .unwrap_or(String::from("404 Not Found"))
-                            .as_bytes(),
-                    )
-                    .unwrap();
-            }
-            Err(Error::Break(info)) => {
-                *res.status_mut() = StatusCode::BadRequest;
-                //return res.send(&"Bad Request".as_bytes()).unwrap();
-                return res.send(&info.as_bytes()).unwrap();
-            }
-            Err(Error::Unauthorized) => {
-                *res.status_mut() = StatusCode::Unauthorized;
-                return res.send(&"Unauthorized".as_bytes()).unwrap();
-            }
-            Err(Error::Forbidden) => {
-                *res.status_mut() = StatusCode::Forbidden;
-                return res.send(&"Forbidden".as_bytes()).unwrap();
             }
-            Err(Error::InternalServerError(info)) => {
-                *res.status_mut() = StatusCode::InternalServerError;
-                //return res.send(&"Internal Server Error".as_bytes()).unwrap();
-                return res.send(&info.as_bytes()).unwrap();
-            }
-            Err(Error::Found(new_uri)) => {
-                *res.status_mut() = StatusCode::Found;
-                res.headers_mut()
-                    .set_raw("Location", vec![new_uri.as_bytes().to_vec()]);
-                return res.send(&"Found, Redirect".as_bytes()).unwrap();
+        }
+
+        // not through the module armors, the global middlewares wrap them,
+        // so they can answer preflights
+        for (host, glob, mut methods, stack) in allowed {
+            if methods.contains(&Method::Options) {
+                continue;
             }
-            Err(Error::TemporaryRedirect(new_uri)) => {
-                *res.status_mut() = StatusCode::TemporaryRedirect;
-                res.headers_mut()
-                    .set_raw("Location", vec![new_uri.as_bytes().to_vec()]);
-                return res.send(&"Temporary Redirect".as_bytes()).unwrap();
+            methods.push(Method::Options);
+            // the routers are hash maps, sorted to answer the same each run
+            methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            let allow = methods
+                .iter()
+                .map(|method| method.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let handler: Arc<Box<SapperHandler>> = Arc::new(Box::new(
+                move |req: &mut SapperRequest| -> Result<SapperResponse> {
+                    let mut response = SapperResponse::new();
+                    response.set_status(StatusCode::NoContent);
+                    response
+                        .headers_mut()
+                        .set_raw("Allow", vec![allow.as_bytes().to_vec()]);
+                    Ok(response)
+                },
+            ));
+            routers.route_with(host, Method::Options, glob, Vec::new(), stack.wrap(handler));
+        }
+
+        if let Some(ref path) = self.health_path {
+            let modules = health;
+            routers.route(
+                Method::Get,
+                path,
+                Arc::new(Box::new(
+                    move |req: &mut SapperRequest| -> Result<SapperResponse> {
+                        Ok(health_response(&modules))
+                    },
+                )),
+            );
+        }
+
+        (routers, lifecycle)
+    }
+
+    // build the app and start all modules, when one fails to start,
+    // the ones started already are shut down again
+    pub fn start(&mut self) -> Result<()> {
+        self.build();
+
+        for (i, sm) in self.lifecycle.iter().enumerate() {
+            if let Err(err) = sm.on_start() {
+                shutdown_modules(&self.lifecycle[..i]);
+                return Err(err);
             }
-            Err(Error::Custom(ustr)) => {
-                *res.status_mut() = StatusCode::Ok;
-                return res.send(&ustr.as_bytes()).unwrap();
+        }
+        Ok(())
+    }
+
+    // start the app, and serve it by http without blocking, when it can
+    // not listen, the modules started are shut down again
+    pub fn start_http(mut self) -> Result<SapperServer> {
+        self.start()?;
+        let addr = self.address.clone() + ":" + &self.port.to_string();
+        let modules = self.lifecycle.clone();
+        let shutdown = self.shutdown.clone();
+
+        match Server::http(&addr[..]).and_then(|server| server.handle(self)) {
+            Ok(listening) => Ok(SapperServer {
+                listening: listening,
+                modules: modules,
+                shutdown: shutdown,
+            }),
+            Err(err) => {
+                shutdown_modules(&modules);
+                Err(Error::Custom(format!("listen on {} error: {}", addr, err)))
             }
-            Err(Error::CustomHtml(html_str)) => {
-                *res.status_mut() = StatusCode::Ok;
-                res.headers_mut()
-                    .set_raw("Content-Type", vec!["text/html".as_bytes().to_vec()]);
-                return res.send(&html_str.as_bytes()).unwrap();
+        }
+    }
+
+    fn warn_if_built(&self, method: &str) {
+        if self.built {
+            warn!(
+                "SapperApp::{} called after the app was built, it takes no effect until the app is built again",
+                method
+            );
+        }
+    }
+
+    // run http server, blocking until the process exits: hyper never stops
+    // serving by itself, so the modules' `on_shutdown` is not run, serve by
+    // `start_http` and call `SapperServer::shutdown` for it
+    pub fn run_http(self) -> Result<()> {
+        self.start_http()?.join();
+        Ok(())
+    }
+}
+
+/// Sapper server struct, a running app
+pub struct SapperServer {
+    listening: Listening,
+    modules: Vec<Arc<Box<SapperModule>>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl SapperServer {
+    /// the address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listening.socket
+    }
+
+    /// stop serving, and shut down all modules: the requests still coming
+    /// are answered `503 Service Unavailable`; hyper can not stop the
+    /// threads listening, they end with the process, so exit it after
+    pub fn shutdown(mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        let _ = self.listening.close();
+        shutdown_modules(&self.modules);
+    }
+
+    /// block until the server stops, then shut down all modules
+    pub fn join(self) {
+        let SapperServer {
+            listening, modules, ..
+        } = self;
+        drop(listening);
+        shutdown_modules(&modules);
+    }
+}
+
+// shut down modules in reverse order, failures are only logged
+fn shutdown_modules(modules: &[Arc<Box<SapperModule>>]) {
+    for sm in modules.iter().rev() {
+        if let Err(err) = sm.on_shutdown() {
+            error!("module shutdown error: {:?}", err);
+        }
+    }
+}
+
+fn health_response(modules: &[(String, Arc<Box<SapperModule>>)]) -> SapperResponse {
+    let failures: Vec<String> = modules
+        .iter()
+        .filter_map(|&(ref mount, ref sm)| {
+            sm.health()
+                .err()
+                .map(|err| format!("{} at {}: {:?}", sm.name(), mount, err))
+        })
+        .collect();
+
+    let mut response = SapperResponse::new();
+    if failures.is_empty() {
+        response.write_body("ok".to_owned());
+    } else {
+        response.set_status(StatusCode::ServiceUnavailable);
+        response.write_body(failures.join("\n"));
+    }
+    response
+}
+
+impl SapperApp {
+    // the response to a request, from the routers, the static files
+    // or the 404 page, through the global middlewares
+    pub(crate) fn dispatch(&self, sreq: &mut SapperRequest) -> SapperResponse {
+        sreq.set_proxies(self.proxies.clone());
+        let (routers, stack) = if self.built {
+            (&self.routers, &self.stack)
+        } else {
+            let built = self
+                .lazy_routers
+                .get_or_init(|| (self.build_routers().0, self.build_stack()));
+            (&built.0, &built.1)
+        };
+
+        let response_w = stack.run(sreq, &|sreq: &mut SapperRequest| self.route(routers, sreq));
+        let mut sres = match response_w {
+            Ok(sres) => sres,
+            Err(err) => sreq.error_response(&err),
+        };
+
+        // the rest of a body not read would be taken for the next request
+        // on the connection, so it is not kept alive
+        if !sreq.body_complete() || sres.status() == StatusCode::PayloadTooLarge {
+            sres.headers_mut()
+                .set_raw("Connection", vec![b"close".to_vec()]);
+        }
+        sres.write_cookies();
+        sres
+    }
+}
+
+impl SapperApp {
+    // pass req to routers, execute matched biz handler, once its body is
+    // decoded, or else answer it by the static files
+    fn route(&self, routers: &Router, sreq: &mut SapperRequest) -> Result<SapperResponse> {
+        sreq.decode_body(self.body_limit)?;
+        let path = sreq.path().to_owned();
+
+        let err = match routers.handle_method(sreq, &path) {
+            Ok(mut sres) => {
+                self.write_not_found(&mut sres);
+                return Ok(sres);
             }
-            Err(Error::CustomJson(json_str)) => {
-                *res.status_mut() = StatusCode::Ok;
-                res.headers_mut().set_raw(
-                    "Content-Type",
-                    vec!["application/x-javascript".as_bytes().to_vec()],
-                );
-                return res.send(&json_str.as_bytes()).unwrap();
+            Err(Error::NotFound) if self.static_file_service => {
+                match simple_file_get(&self.static_dir, &path) {
+                    Ok((file_u8vec, file_mime)) => {
+                        let mut sres = SapperResponse::new();
+                        sres.headers_mut()
+                            .set_raw("Content-Type", vec![file_mime.as_bytes().to_vec()]);
+                        sres.write_raw_body(file_u8vec);
+                        return Ok(sres);
+                    }
+                    Err(_) => Error::NotFound,
+                }
             }
-            Err(_) => {
-                *res.status_mut() = StatusCode::InternalServerError;
-                return res.send(&"InternalServerError".as_bytes()).unwrap();
+            Err(err) => err,
+        };
+
+        // the error goes on through the global middlewares, with the 404
+        // page as the response it is answered with
+        if err == Error::NotFound {
+            let mut sres = sreq.error_response(&err);
+            self.write_not_found(&mut sres);
+            sreq.set_error_response(err.clone(), sres);
+        }
+        Err(err)
+    }
+
+    // 404 NotFound without a body of its own gets the 404 page
+    fn write_not_found(&self, sres: &mut SapperResponse) {
+        if sres.status() == StatusCode::NotFound && sres.body().is_none() && !sres.is_streaming() {
+            sres.write_body(
+                self.not_found
+                    .to_owned()
+                    .unwrap_or(String::from("404 Not Found")),
+            );
+        }
+    }
+}
+
+impl Handler for SapperApp {
+    /// do actual handling for a request
+    fn handle(&self, req: Request, mut res: Response) {
+        if self.shutdown.load(Ordering::SeqCst) {
+            *res.status_mut() = StatusCode::ServiceUnavailable;
+            res.headers_mut()
+                .set_raw("Connection", vec![b"close".to_vec()]);
+            let _ = res.send(b"");
+            return;
+        }
+        let mut sreq = SapperRequest::from_hyper(req, self.body_limit);
+        let mut sres = self.dispatch(&mut sreq);
+
+        *res.status_mut() = sres.status();
+        for (name, values) in sres.headers().iter() {
+            res.headers_mut().set_raw(name.to_owned(), values.to_vec());
+        }
+        if let Some(mut stream) = sres.take_stream() {
+            // without a content length, hyper sends it chunked
+            let sent = res
+                .start()
+                .and_then(|mut res| io::copy(&mut stream, &mut res).and_then(|_| res.end()));
+            if let Err(err) = sent {
+                error!("response stream writing error: {}", err);
             }
+            return;
+        }
+        match sres.body() {
+            &Some(ref vec) => res.send(&vec[..]).unwrap(),
+            &None => res.send(&"".as_bytes()).unwrap(),
         }
     }
 }
@@ -319,12 +681,12 @@ lazy_static! {
 }
 
 // a simple static file service
-fn simple_file_get(path: &str) -> Result<(Vec<u8>, String)> {
+fn simple_file_get(dir: &str, path: &str) -> Result<(Vec<u8>, String)> {
     let new_path;
     if &path[(path.len() - 1)..] == "/" {
-        new_path = "static/".to_owned() + path + "index.html";
+        new_path = dir.to_owned() + "/" + path + "index.html";
     } else {
-        new_path = "static/".to_owned() + path;
+        new_path = dir.to_owned() + "/" + path;
     }
     //println!("file path: {}", new_path);
     match File::open(&new_path) {
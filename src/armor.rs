@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use app::{Result, SapperModule};
+use middleware::SapperMiddleware;
+use request::SapperRequest;
+use response::SapperResponse;
+
+/// Sapper armor trait, used to place before and after middlewares,
+/// armors and middlewares stack in the order added: `before` are executed
+/// in that order, `after` in reverse
+pub trait SapperArmor {
+    fn before(&self, &mut SapperRequest) -> Result<()>;
+    fn after(&self, &SapperRequest, &mut SapperResponse) -> Result<()>;
+
+    /// like `before`, but may answer the request by itself: returning
+    /// `Ok(Some(response))` skips the inner armors and the handler, and only
+    /// the `after` of the outer armors are executed on the response
+    fn intercept(&self, req: &mut SapperRequest) -> Result<Option<SapperResponse>> {
+        self.before(req).map(|_| None)
+    }
+
+    /// whether `after` is also executed when the inner armors or the handler
+    /// fail, on the response the error is answered with; the error still
+    /// goes on to the outer layers, and the app answers it with that
+    /// response; return `false` to skip `after` on errors
+    fn after_on_error(&self) -> bool {
+        true
+    }
+}
+
+pub type SapperArmorType = Box<SapperArmor + 'static + Send + Sync>;
+
+/// An armor as a middleware, `after` is not executed when the armor
+/// intercepts the request, nor when its own `before` fails
+pub struct ArmorMiddleware(pub SapperArmorType);
+
+impl SapperMiddleware for ArmorMiddleware {
+    fn handle(
+        &self,
+        req: &mut SapperRequest,
+        next: &Fn(&mut SapperRequest) -> Result<SapperResponse>,
+    ) -> Result<SapperResponse> {
+        if let Some(response) = self.0.intercept(req)? {
+            return Ok(response);
+        }
+
+        match next(req) {
+            Ok(mut response) => {
+                self.0.after(req, &mut response)?;
+                Ok(response)
+            }
+            Err(err) => {
+                if self.0.after_on_error() {
+                    // the error goes on, so outer middlewares can still
+                    // catch it, with the response `after` made of it kept
+                    let mut response = req.error_response(&err);
+                    self.0.after(req, &mut response)?;
+                    req.set_error_response(err.clone(), response);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+/// An armor made of a before filter only
+pub struct BeforeArmor<F>(pub F);
+
+impl<F> SapperArmor for BeforeArmor<F>
+where
+    F: Fn(&mut SapperRequest) -> Result<()>,
+{
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        (self.0)(req)
+    }
+
+    fn after(&self, req: &SapperRequest, res: &mut SapperResponse) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An armor made of an after filter only
+pub struct AfterArmor<F>(pub F);
+
+impl<F> SapperArmor for AfterArmor<F>
+where
+    F: Fn(&SapperRequest, &mut SapperResponse) -> Result<()>,
+{
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        Ok(())
+    }
+
+    fn after(&self, req: &SapperRequest, res: &mut SapperResponse) -> Result<()> {
+        (self.0)(req, res)
+    }
+}
+
+/// The before and after of a module, as an armor
+pub struct ModuleArmor(pub Arc<Box<SapperModule>>);
+
+impl SapperArmor for ModuleArmor {
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        self.0.before(req)
+    }
+
+    fn after(&self, req: &SapperRequest, res: &mut SapperResponse) -> Result<()> {
+        self.0.after(req, res)
+    }
+
+    fn after_on_error(&self) -> bool {
+        self.0.after_on_error()
+    }
+}
+
+#[cfg(test)]
+use app::SapperApp;
+#[cfg(test)]
+use router::SapperRouter;
+#[cfg(test)]
+use test_client::{trace, trace_after, traced, TestClient, TraceArmor};
+
+#[cfg(test)]
+struct Stacked;
+
+#[cfg(test)]
+impl SapperModule for Stacked {
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        trace(req, "module");
+        Ok(())
+    }
+
+    fn after(&self, req: &SapperRequest, res: &mut SapperResponse) -> Result<()> {
+        trace_after(res, "module after");
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router
+            .armor(Box::new(TraceArmor("router")))
+            .get("/steps", traced);
+        Ok(())
+    }
+}
+
+#[test]
+fn armor_order() {
+    let mut app = SapperApp::new();
+    app.with_armor(Box::new(TraceArmor("a")))
+        .with_armor(Box::new(TraceArmor("b")))
+        .add_module(Box::new(Stacked));
+    let client = TestClient::new(app);
+
+    // `before` in the order added, `after` in reverse
+    assert_eq!(
+        client.get("/steps").send().text(),
+        "a,b,module,router,handler,router after,module after,b after,a after"
+    );
+
+    // an intercepting armor skips the inner layers, the outer ones still
+    // see its response
+    let res = client.get("/steps").header("X-Stop", "b").send();
+    assert_eq!(res.text(), "a,b,a after");
+    let res = client.get("/steps").header("X-Stop", "router").send();
+    assert_eq!(res.text(), "a,b,module,router,module after,b after,a after");
+}
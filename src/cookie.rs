@@ -0,0 +1,306 @@
+use std::fmt;
+use std::slice;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The `SameSite` attribute of a cookie
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// A http cookie, as sent by the client, or to set on the client
+///
+/// ```ignore
+/// let cookie = Cookie::new("theme", "dark")
+///     .path("/")
+///     .http_only(true)
+///     .same_site(SameSite::Lax);
+/// res.cookies_mut().add(cookie);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    expires: Option<SystemTime>,
+    max_age: Option<i64>,
+    domain: Option<String>,
+    path: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    partitioned: bool,
+}
+
+impl Cookie {
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Cookie {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            partitioned: false,
+        }
+    }
+
+    /// parse one `name=value` pair of a `Cookie` header
+    pub fn parse(pair: &str) -> Option<Cookie> {
+        let mut kv = pair.trim().splitn(2, '=');
+        let name = kv.next()?.trim();
+        let value = kv.next()?.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+        Some(Cookie::new(name, value))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn set_value<V: Into<String>>(&mut self, value: V) {
+        self.value = value.into();
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// seconds the cookie lives, `0` removes it
+    pub fn max_age(mut self, seconds: i64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn domain<S: Into<String>>(mut self, domain: S) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub fn path<S: Into<String>>(mut self, path: S) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// `SameSite::None` implies `Secure`, as browsers require
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// store the cookie partitioned by the top level site (CHIPS),
+    /// implies `Secure`, as browsers require
+    pub fn partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    // the same cookie on the client, which has the same name, domain and path
+    fn same_as(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    // the cookie which removes this one from the client
+    fn removal(&self) -> Cookie {
+        let mut removal = Cookie::new(self.name.clone(), "");
+        removal.domain = self.domain.clone();
+        removal.path = self.path.clone();
+        removal.max_age(0).expires(UNIX_EPOCH)
+    }
+}
+
+/// The `Set-Cookie` value of the cookie
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if let Some(ref domain) = self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if self.secure || self.partitioned || self.same_site == Some(SameSite::None) {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => f.write_str("; SameSite=Strict")?,
+            Some(SameSite::Lax) => f.write_str("; SameSite=Lax")?,
+            Some(SameSite::None) => f.write_str("; SameSite=None")?,
+            None => {}
+        }
+        if self.partitioned {
+            f.write_str("; Partitioned")?;
+        }
+        Ok(())
+    }
+}
+
+/// A set of cookies: the ones a request sent, or the ones a response sets,
+/// each is written as a `Set-Cookie` header of its own
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar {
+            cookies: Vec::new(),
+        }
+    }
+
+    /// the cookies of `Cookie` header values, `a=1; b=2`
+    pub fn parse<'a, I: IntoIterator<Item = &'a [u8]>>(values: I) -> CookieJar {
+        let mut jar = CookieJar::new();
+        for value in values {
+            let value = String::from_utf8_lossy(value);
+            jar.cookies
+                .extend(value.split(';').filter_map(Cookie::parse));
+        }
+        jar
+    }
+
+    /// the first cookie named `name`
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.cookies.iter().find(|cookie| cookie.name == name)
+    }
+
+    /// add `cookie`, replacing the one with the same name, domain and path
+    pub fn add(&mut self, cookie: Cookie) {
+        match self.cookies.iter().position(|c| c.same_as(&cookie)) {
+            Some(i) => self.cookies[i] = cookie,
+            None => self.cookies.push(cookie),
+        }
+    }
+
+    /// remove `cookie` from the client, it must have the domain and path
+    /// it was set with
+    pub fn remove(&mut self, cookie: Cookie) {
+        self.add(cookie.removal());
+    }
+
+    pub fn iter(&self) -> slice::Iter<Cookie> {
+        self.cookies.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+// IMF-fixdate, `Sun, 06 Nov 1994 08:49:37 GMT`
+fn http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs();
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[test]
+fn cookie_attributes() {
+    let cookie = Cookie::new("id", "a3fWa")
+        .expires(UNIX_EPOCH + Duration::from_secs(784_111_777))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .partitioned(true);
+    assert_eq!(
+        cookie.to_string(),
+        "id=a3fWa; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Path=/; Secure; HttpOnly; \
+         SameSite=Strict; Partitioned"
+    );
+    assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    assert_eq!(
+        http_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+        "Tue, 29 Feb 2000 00:00:00 GMT"
+    );
+}
+
+#[test]
+fn cookie_jar() {
+    let jar = CookieJar::parse(vec![&b"a=1; b=\"2\""[..], &b"c=3;;bad"[..]]);
+    assert_eq!(jar.len(), 3);
+    assert_eq!(jar.get("b").map(|c| c.value()), Some("2"));
+
+    let mut jar = CookieJar::new();
+    jar.add(Cookie::new("a", "1").path("/"));
+    jar.add(Cookie::new("b", "2"));
+    jar.add(Cookie::new("a", "3").path("/"));
+    jar.remove(Cookie::new("b", ""));
+    let lines: Vec<String> = jar.iter().map(|c| c.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "a=3; Path=/",
+            "b=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0",
+        ]
+    );
+}
@@ -0,0 +1,90 @@
+use std::io::Read;
+
+use brotli::Decompressor;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+
+use app::{Error, Result};
+
+const BUFFER_SIZE: usize = 4096;
+
+/// the body decoded by the `Content-Encoding` codings it was encoded with,
+/// `PayloadTooLarge` when it decodes to more than `limit` bytes, so a small
+/// body can not inflate to exhaust the memory
+pub fn decode(codings: &str, mut body: Vec<u8>, limit: usize) -> Result<Vec<u8>> {
+    // the codings are listed in the order they were applied
+    let codings: Vec<String> = codings
+        .split(',')
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect();
+
+    for coding in codings.iter().rev() {
+        body = match coding.as_str() {
+            "gzip" | "x-gzip" => read_limited(GzDecoder::new(&body[..]), limit)?,
+            // the `deflate` coding is zlib, but some clients send raw deflate
+            "deflate" if zlib_header(&body) => read_limited(ZlibDecoder::new(&body[..]), limit)?,
+            "deflate" => read_limited(DeflateDecoder::new(&body[..]), limit)?,
+            "br" => read_limited(Decompressor::new(&body[..], BUFFER_SIZE), limit)?,
+            _ => return Err(Error::UnsupportedMediaType),
+        };
+    }
+    Ok(body)
+}
+
+fn read_limited<R: Read>(reader: R, limit: usize) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|err| Error::Break(format!("invalid request body encoding: {}", err)))?;
+    if decoded.len() > limit {
+        return Err(Error::PayloadTooLarge);
+    }
+    Ok(decoded)
+}
+
+// a zlib stream starts with the deflate method and a header checksum
+fn zlib_header(body: &[u8]) -> bool {
+    body.len() >= 2
+        && body[0] & 0x0f == 8
+        && (u16::from(body[0]) << 8 | u16::from(body[1])) % 31 == 0
+}
+
+#[cfg(test)]
+pub fn gzip(body: &[u8]) -> Vec<u8> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn decode_codings() {
+    let body = b"name=sapper&kind=framework".to_vec();
+    assert_eq!(decode("gzip", gzip(&body), 1024).unwrap(), body);
+    assert_eq!(decode("identity", body.clone(), 1024).unwrap(), body);
+    assert_eq!(
+        decode("gzip, gzip", gzip(&gzip(&body)), 1024).unwrap(),
+        body
+    );
+
+    match decode("compress", body.clone(), 1024) {
+        Err(Error::UnsupportedMediaType) => {}
+        _ => panic!("unknown coding decoded"),
+    }
+    match decode("gzip", body.clone(), 1024) {
+        Err(Error::Break(_)) => {}
+        _ => panic!("invalid gzip decoded"),
+    }
+
+    // a megabyte of zeros compresses to a kilobyte
+    let bomb = gzip(&vec![0; 1 << 20]);
+    assert!(bomb.len() < 2048);
+    match decode("gzip", bomb, 64 * 1024) {
+        Err(Error::PayloadTooLarge) => {}
+        _ => panic!("body over the limit decoded"),
+    }
+}
@@ -0,0 +1,196 @@
+use std::any::Any;
+use std::str;
+
+use request::SapperRequest as Request;
+
+/// All route guards should implement this Guard trait,
+/// a route only matches when all of its guards pass
+pub trait SapperGuard: Send + Sync + Any {
+    fn check(&self, &Request) -> bool;
+}
+
+impl<F> SapperGuard for F
+where
+    F: Send + Sync + Any + Fn(&Request) -> bool,
+{
+    fn check(&self, req: &Request) -> bool {
+        (*self)(req)
+    }
+}
+
+/// the `Accept` header explicitly lists `media`, e.g. `application/vnd.api.v2+json`,
+/// wildcards like `*/*` don't satisfy it
+pub fn accept(media: &'static str) -> impl SapperGuard {
+    move |req: &Request| {
+        header_items(req, "Accept")
+            .iter()
+            .any(|item| media_type(item).eq_ignore_ascii_case(media) && !q_zero(item))
+    }
+}
+
+/// the `Content-Type` of the request body is `media`, parameters are ignored
+pub fn content_type(media: &'static str) -> impl SapperGuard {
+    move |req: &Request| {
+        header_items(req, "Content-Type")
+            .first()
+            .map(|item| media_type(item).eq_ignore_ascii_case(media))
+            .unwrap_or(false)
+    }
+}
+
+/// the request has a header named `name`
+pub fn header(name: &'static str) -> impl SapperGuard {
+    move |req: &Request| req.headers().get_raw(name).is_some()
+}
+
+/// the request has a header named `name` with the value `value`
+pub fn header_value(name: &'static str, value: &'static str) -> impl SapperGuard {
+    move |req: &Request| match req.headers().get_raw(name) {
+        Some(raws) => raws.iter().any(|raw| {
+            str::from_utf8(raw)
+                .map(|v| v.trim() == value)
+                .unwrap_or(false)
+        }),
+        None => false,
+    }
+}
+
+/// the query string has the parameter `flag`, with or without a value
+pub fn query(flag: &'static str) -> impl SapperGuard {
+    move |req: &Request| match req.query() {
+        Some(query) => query
+            .split('&')
+            .any(|pair| pair.split('=').next() == Some(flag)),
+        None => false,
+    }
+}
+
+// comma separated items of all the values of header `name`
+fn header_items(req: &Request, name: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    if let Some(raws) = req.headers().get_raw(name) {
+        for raw in raws.iter() {
+            if let Ok(value) = str::from_utf8(raw) {
+                items.extend(value.split(',').map(|item| item.trim().to_owned()));
+            }
+        }
+    }
+    items
+}
+
+fn media_type(item: &str) -> &str {
+    item.split(';').next().unwrap_or("").trim()
+}
+
+fn q_zero(item: &str) -> bool {
+    item.split(';').skip(1).any(|param| {
+        let mut kv = param.splitn(2, '=');
+        kv.next().map(|k| k.trim()) == Some("q")
+            && kv.next().and_then(|v| v.trim().parse::<f32>().ok()) == Some(0.0)
+    })
+}
+
+#[test]
+fn guards() {
+    let req = Request::builder()
+        .uri("/docs?draft&page=2")
+        .header("Accept", "text/html, application/vnd.api.v2+json;q=0.9")
+        .header("Content-Type", "application/json; charset=utf-8")
+        .header("X-Version", " 2 ")
+        .build();
+
+    assert!(accept("application/vnd.api.v2+json").check(&req));
+    assert!(!accept("application/json").check(&req));
+    assert!(content_type("application/json").check(&req));
+    assert!(!content_type("text/plain").check(&req));
+    assert!(header("X-Version").check(&req));
+    assert!(!header("X-Missing").check(&req));
+    assert!(header_value("X-Version", "2").check(&req));
+    assert!(!header_value("X-Version", "1").check(&req));
+    assert!(query("draft").check(&req));
+    assert!(query("page").check(&req));
+    assert!(!query("drafts").check(&req));
+
+    // wildcards and refused types don't satisfy `accept`
+    let req = Request::builder()
+        .header("Accept", "*/*, application/vnd.api.v2+json;q=0")
+        .build();
+    assert!(!accept("application/vnd.api.v2+json").check(&req));
+}
+
+#[cfg(test)]
+use app::{Result, SapperApp, SapperModule};
+#[cfg(test)]
+use http::Method;
+#[cfg(test)]
+use hyper::status::StatusCode;
+#[cfg(test)]
+use response::SapperResponse as Response;
+#[cfg(test)]
+use router::SapperRouter;
+#[cfg(test)]
+use test_client::TestClient;
+
+#[cfg(test)]
+struct Versioned;
+
+#[cfg(test)]
+impl SapperModule for Versioned {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router
+            .route_guarded(
+                Method::Get,
+                "/docs/:id",
+                accept("application/vnd.api.v2+json"),
+                |req: &mut Request| {
+                    let mut res = Response::new();
+                    res.write_body("v2".to_owned());
+                    Ok(res)
+                },
+            )
+            .route_guarded(
+                Method::Get,
+                "/docs/:id",
+                query("draft"),
+                |req: &mut Request| {
+                    let mut res = Response::new();
+                    res.write_body("draft".to_owned());
+                    Ok(res)
+                },
+            )
+            .get("/docs/:id", |req: &mut Request| {
+                let mut res = Response::new();
+                res.write_body("v1".to_owned());
+                Ok(res)
+            })
+            .route_guarded(
+                Method::Get,
+                "/admin",
+                header("X-Admin"),
+                |req: &mut Request| Ok(Response::new()),
+            );
+        Ok(())
+    }
+}
+
+#[test]
+fn route_guards() {
+    let mut app = SapperApp::new();
+    app.add_module(Box::new(Versioned));
+    let client = TestClient::new(app);
+
+    let res = client
+        .get("/docs/1")
+        .header("Accept", "application/vnd.api.v2+json")
+        .send();
+    assert_eq!(res.text(), "v2");
+    // a failing guard falls through to the next route
+    assert_eq!(client.get("/docs/1?draft").send().text(), "draft");
+    assert_eq!(client.get("/docs/1").send().text(), "v1");
+    // and to not found when none is left
+    assert_eq!(client.get("/admin").send().status(), StatusCode::NotFound);
+    assert_eq!(
+        client.get("/admin").header("X-Admin", "1").send().status(),
+        StatusCode::Ok
+    );
+}
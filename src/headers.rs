@@ -0,0 +1,181 @@
+use std::slice;
+
+use hyper::header::{Header, HeaderFormat, Headers};
+
+/// Sapper header map, owns the raw values of each header, names are
+/// case insensitive and keep the order they are added in.
+///
+/// The typed headers in `sapper::header` are parsed and formatted on access,
+/// so `get::<ContentType>()` and `set(Location(..))` work as before.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, Vec<Vec<u8>>)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap {
+            entries: Vec::new(),
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|&(ref n, _)| n.eq_ignore_ascii_case(name))
+    }
+
+    /// get the raw values of header `name`
+    pub fn get_raw(&self, name: &str) -> Option<&[Vec<u8>]> {
+        self.position(name).map(|i| &self.entries[i].1[..])
+    }
+
+    /// get the first value of header `name`, as a string
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get_raw(name)
+            .and_then(|values| values.first())
+            .and_then(|value| ::std::str::from_utf8(value).ok())
+    }
+
+    /// set the raw values of header `name`, replacing the values it had
+    pub fn set_raw<K: Into<String>>(&mut self, name: K, values: Vec<Vec<u8>>) {
+        let name = name.into();
+        match self.position(&name) {
+            Some(i) => self.entries[i].1 = values,
+            None => self.entries.push((name, values)),
+        }
+    }
+
+    /// add a raw value to header `name`, after the values it has
+    pub fn append_raw<K: Into<String>>(&mut self, name: K, value: Vec<u8>) {
+        let name = name.into();
+        match self.position(&name) {
+            Some(i) => self.entries[i].1.push(value),
+            None => self.entries.push((name, vec![value])),
+        }
+    }
+
+    /// remove header `name`, return whether it was there
+    pub fn remove_raw(&mut self, name: &str) -> bool {
+        match self.position(name) {
+            Some(i) => {
+                self.entries.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// whether there is a header `name`
+    pub fn contains(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    /// parse the typed header `H`, `None` when it is missing or malformed
+    pub fn get<H: Header + HeaderFormat>(&self) -> Option<H> {
+        self.get_raw(H::header_name())
+            .and_then(|values| H::parse_header(values).ok())
+    }
+
+    /// set the typed header `H`, replacing the values it had
+    pub fn set<H: Header + HeaderFormat>(&mut self, header: H) {
+        // formatted by hyper, headers such as `SetCookie` span several lines
+        let mut headers = Headers::new();
+        headers.set(header);
+        let text = headers.to_string();
+        let values = text
+            .split("\r\n")
+            .filter_map(|line| line.splitn(2, ": ").nth(1))
+            .map(|value| value.as_bytes().to_vec())
+            .collect();
+        self.set_raw(H::header_name(), values);
+    }
+
+    /// whether there is a typed header `H`
+    pub fn has<H: Header + HeaderFormat>(&self) -> bool {
+        self.contains(H::header_name())
+    }
+
+    /// remove the typed header `H`, return whether it was there
+    pub fn remove<H: Header + HeaderFormat>(&mut self) -> bool {
+        self.remove_raw(H::header_name())
+    }
+
+    /// iterate the headers, with all their raw values
+    pub fn iter(&self) -> Iter {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    /// the number of headers
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Iterator on the headers of a `HeaderMap`
+pub struct Iter<'a> {
+    inner: slice::Iter<'a, (String, Vec<Vec<u8>>)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a [Vec<u8>]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|&(ref name, ref values)| (&name[..], &values[..]))
+    }
+}
+
+impl<'a> From<&'a Headers> for HeaderMap {
+    fn from(headers: &'a Headers) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for view in headers.iter() {
+            if let Some(values) = headers.get_raw(view.name()) {
+                map.set_raw(view.name(), values.to_vec());
+            }
+        }
+        map
+    }
+}
+
+impl<'a> From<&'a HeaderMap> for Headers {
+    fn from(map: &'a HeaderMap) -> Headers {
+        let mut headers = Headers::new();
+        for (name, values) in map.iter() {
+            headers.set_raw(name.to_owned(), values.to_vec());
+        }
+        headers
+    }
+}
+
+#[test]
+fn header_map() {
+    use hyper::header::{ContentLength, SetCookie};
+
+    let mut map = HeaderMap::new();
+    map.set(ContentLength(5));
+    map.set(SetCookie(vec!["a=1".to_owned(), "b=2".to_owned()]));
+    map.append_raw("x-tag", b"one".to_vec());
+    map.append_raw("X-Tag", b"two".to_vec());
+
+    assert_eq!(map.get::<ContentLength>(), Some(ContentLength(5)));
+    assert_eq!(map.get_str("content-length"), Some("5"));
+    assert_eq!(map.get_raw("Set-Cookie").unwrap().len(), 2);
+    assert_eq!(
+        map.get_raw("X-TAG").unwrap(),
+        &[b"one".to_vec(), b"two".to_vec()]
+    );
+
+    let headers = Headers::from(&map);
+    assert_eq!(HeaderMap::from(&headers).len(), 3);
+
+    assert!(map.remove::<ContentLength>());
+    assert!(!map.has::<ContentLength>());
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+use std::str::FromStr;
+
+use hyper::method::Method as HyperMethod;
+use hyper::version::HttpVersion;
+
+use app::Error;
+
+/// Http method of a request
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Method {
+    Options,
+    Get,
+    Post,
+    Put,
+    Delete,
+    Head,
+    Trace,
+    Connect,
+    Patch,
+    /// any other method, such as `PROPFIND`
+    Extension(String),
+}
+
+impl Method {
+    /// the method name, as sent in the request line
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Method::Options => "OPTIONS",
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Head => "HEAD",
+            Method::Trace => "TRACE",
+            Method::Connect => "CONNECT",
+            Method::Patch => "PATCH",
+            Method::Extension(ref name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Method, Error> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            return Err(Error::Break(format!("invalid method `{}`", s)));
+        }
+        Ok(match s {
+            "OPTIONS" => Method::Options,
+            "GET" => Method::Get,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "HEAD" => Method::Head,
+            "TRACE" => Method::Trace,
+            "CONNECT" => Method::Connect,
+            "PATCH" => Method::Patch,
+            _ => Method::Extension(s.to_owned()),
+        })
+    }
+}
+
+impl From<HyperMethod> for Method {
+    fn from(method: HyperMethod) -> Method {
+        match method {
+            HyperMethod::Options => Method::Options,
+            HyperMethod::Get => Method::Get,
+            HyperMethod::Post => Method::Post,
+            HyperMethod::Put => Method::Put,
+            HyperMethod::Delete => Method::Delete,
+            HyperMethod::Head => Method::Head,
+            HyperMethod::Trace => Method::Trace,
+            HyperMethod::Connect => Method::Connect,
+            HyperMethod::Patch => Method::Patch,
+            HyperMethod::Extension(name) => Method::Extension(name),
+        }
+    }
+}
+
+impl From<Method> for HyperMethod {
+    fn from(method: Method) -> HyperMethod {
+        match method {
+            Method::Options => HyperMethod::Options,
+            Method::Get => HyperMethod::Get,
+            Method::Post => HyperMethod::Post,
+            Method::Put => HyperMethod::Put,
+            Method::Delete => HyperMethod::Delete,
+            Method::Head => HyperMethod::Head,
+            Method::Trace => HyperMethod::Trace,
+            Method::Connect => HyperMethod::Connect,
+            Method::Patch => HyperMethod::Patch,
+            Method::Extension(name) => HyperMethod::Extension(name),
+        }
+    }
+}
+
+/// Http version of a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Version {
+    Http09,
+    Http10,
+    Http11,
+    Http20,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Version::Http09 => "HTTP/0.9",
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+            Version::Http20 => "HTTP/2.0",
+        })
+    }
+}
+
+impl From<HttpVersion> for Version {
+    fn from(version: HttpVersion) -> Version {
+        match version {
+            HttpVersion::Http09 => Version::Http09,
+            HttpVersion::Http10 => Version::Http10,
+            HttpVersion::Http11 => Version::Http11,
+            HttpVersion::Http20 => Version::Http20,
+        }
+    }
+}
+
+impl From<Version> for HttpVersion {
+    fn from(version: Version) -> HttpVersion {
+        match version {
+            Version::Http09 => HttpVersion::Http09,
+            Version::Http10 => HttpVersion::Http10,
+            Version::Http11 => HttpVersion::Http11,
+            Version::Http20 => HttpVersion::Http20,
+        }
+    }
+}
+
+#[test]
+fn method_names() {
+    assert_eq!("GET".parse::<Method>().unwrap(), Method::Get);
+    assert_eq!(
+        "PROPFIND".parse::<Method>().unwrap(),
+        Method::Extension("PROPFIND".to_owned())
+    );
+    assert!("GE T".parse::<Method>().is_err());
+    assert_eq!(Method::from(HyperMethod::Patch).to_string(), "PATCH");
+}
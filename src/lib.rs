@@ -5,30 +5,60 @@
 extern crate log;
 #[macro_use]
 extern crate lazy_static;
+extern crate brotli;
 extern crate conduit_mime_types as mime_types;
+extern crate flate2;
 extern crate hyper;
 extern crate typemap;
 
 mod app;
+mod armor;
+mod cookie;
+mod decompress;
+pub mod guard;
 mod handler;
-mod recognizer;
+mod headers;
+mod http;
+mod middleware;
+mod negotiate;
+mod proxy;
+#[doc(hidden)]
+pub mod recognizer;
 mod request;
 mod response;
 mod router;
 mod router_m;
+mod state;
+mod test_client;
+mod uri;
 
 /// reexport hyper's Client to sapper level
 pub use app::Client;
+/// HostParams is the parameter type referring the parameters collected in host
+pub use app::HostParams;
+/// MountPrefix is the path prefix the handling module was mounted under,
+/// with the prefixes of the groups and nested modules of the route
+pub use app::MountPrefix;
 /// PathParams is the parameter type referring the parameters collected in url
 pub use app::PathParams;
 pub use app::SapperApp as App;
 pub use app::SapperArmor as Armor;
+pub use app::SapperGuard as Guard;
 pub use app::SapperHandler as Handler;
+pub use app::SapperMiddleware as Middleware;
 pub use app::SapperModule as Module;
 pub use app::SapperRequest as Request;
+pub use app::SapperRequestBuilder as RequestBuilder;
 pub use app::SapperResponse as Response;
 pub use app::SapperRouter as Router;
+pub use app::SapperServer as Server;
 pub use app::{header, mime, status};
+/// cookies of requests and responses
+pub use app::{Cookie, CookieJar, SameSite};
 pub use app::{Error, Key, Result};
+/// sapper's own http types, independent of the server backend
+pub use app::{HeaderMap, Method, Uri, Version};
 
 pub use recognizer::Params;
+/// in process client for testing an app without sockets
+pub use test_client::{TestClient, TestRequest, TestResponse};
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use app::Result;
+use armor::{ArmorMiddleware, SapperArmorType};
+use handler::SapperHandler;
+use request::SapperRequest;
+use response::SapperResponse;
+
+/// Sapper middleware trait, wrapping the call of the handler: `next` executes
+/// the inner middlewares and the handler, so a middleware can time, retry or
+/// skip it, and inspect or replace its result
+pub trait SapperMiddleware: Send + Sync {
+    fn handle(
+        &self,
+        req: &mut SapperRequest,
+        next: &Fn(&mut SapperRequest) -> Result<SapperResponse>,
+    ) -> Result<SapperResponse>;
+}
+
+impl<F> SapperMiddleware for F
+where
+    F: Send
+        + Sync
+        + Fn(
+            &mut SapperRequest,
+            &Fn(&mut SapperRequest) -> Result<SapperResponse>,
+        ) -> Result<SapperResponse>,
+{
+    fn handle(
+        &self,
+        req: &mut SapperRequest,
+        next: &Fn(&mut SapperRequest) -> Result<SapperResponse>,
+    ) -> Result<SapperResponse> {
+        (*self)(req, next)
+    }
+}
+
+pub type SapperMiddlewareType = Box<SapperMiddleware + 'static>;
+
+/// An ordered stack of middlewares and armors, wrapping a handler like an
+/// onion, the first pushed is the outermost
+#[derive(Clone)]
+pub struct MiddlewareStack {
+    layers: Vec<Arc<SapperMiddlewareType>>,
+}
+
+impl MiddlewareStack {
+    pub fn new() -> MiddlewareStack {
+        MiddlewareStack { layers: Vec::new() }
+    }
+
+    pub fn push(&mut self, middleware: SapperMiddlewareType) -> &mut MiddlewareStack {
+        self.layers.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn push_armor(&mut self, armor: SapperArmorType) -> &mut MiddlewareStack {
+        self.push(Box::new(ArmorMiddleware(armor)))
+    }
+
+    /// push all the layers of `other` on this stack
+    pub fn extend(&mut self, other: &MiddlewareStack) -> &mut MiddlewareStack {
+        self.layers.extend(other.layers.iter().cloned());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// run `handler` inside this stack
+    pub fn handle(
+        &self,
+        req: &mut SapperRequest,
+        handler: &SapperHandler,
+    ) -> Result<SapperResponse> {
+        self.run(req, &|req: &mut SapperRequest| handler.handle(req))
+    }
+
+    /// run `f` inside this stack, like `handle`, for a closure borrowing
+    /// what it works on
+    pub fn run(
+        &self,
+        req: &mut SapperRequest,
+        f: &Fn(&mut SapperRequest) -> Result<SapperResponse>,
+    ) -> Result<SapperResponse> {
+        self.run_from(0, req, f)
+    }
+
+    fn run_from(
+        &self,
+        index: usize,
+        req: &mut SapperRequest,
+        f: &Fn(&mut SapperRequest) -> Result<SapperResponse>,
+    ) -> Result<SapperResponse> {
+        match self.layers.get(index) {
+            Some(layer) => layer.handle(req, &|req: &mut SapperRequest| {
+                self.run_from(index + 1, req, f)
+            }),
+            None => f(req),
+        }
+    }
+
+    /// get a handler running `handler` inside this stack
+    pub fn wrap(&self, handler: Arc<Box<SapperHandler>>) -> Arc<Box<SapperHandler>> {
+        if self.is_empty() {
+            return handler;
+        }
+
+        let stack = self.clone();
+        Arc::new(Box::new(
+            move |req: &mut SapperRequest| -> Result<SapperResponse> {
+                stack.handle(req, &**handler)
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+use app::{Error, SapperApp, SapperModule};
+#[cfg(test)]
+use router::SapperRouter;
+#[cfg(test)]
+use test_client::{trace, trace_after, traced, TestClient, Trace, TraceArmor};
+
+// a middleware tracing around the handler call
+#[cfg(test)]
+fn trace_middleware(name: &'static str) -> SapperMiddlewareType {
+    Box::new(
+        move |req: &mut SapperRequest, next: &Fn(&mut SapperRequest) -> Result<SapperResponse>| {
+            trace(req, name);
+            let mut res = next(req)?;
+            trace_after(&mut res, &format!("{} after", name));
+            Ok(res)
+        },
+    )
+}
+
+#[cfg(test)]
+struct Wrapped;
+
+#[cfg(test)]
+impl SapperModule for Wrapped {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router
+            .middleware(trace_middleware("router"))
+            .armor(Box::new(TraceArmor("router armor")))
+            .get("/steps", traced);
+        router.get("/flaky", |req: &mut SapperRequest| {
+            trace(req, "try");
+            let tries = req
+                .ext()
+                .get::<Trace>()
+                .unwrap()
+                .iter()
+                .filter(|step| *step == "try");
+            if tries.count() < 2 {
+                return Err(Error::InternalServerError("flaky".to_owned()));
+            }
+            traced(req)
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn middleware_order() {
+    let mut app = SapperApp::new();
+    app.with_armor(Box::new(TraceArmor("a")))
+        .with_middleware(trace_middleware("m"))
+        .with_armor(Box::new(TraceArmor("b")))
+        .with_middleware(Box::new(
+            |req: &mut SapperRequest, next: &Fn(&mut SapperRequest) -> Result<SapperResponse>| {
+                // retried once on errors
+                next(req).or_else(|_| next(req))
+            },
+        ))
+        .add_module(Box::new(Wrapped));
+    let client = TestClient::new(app);
+
+    // middlewares stack with the armors in the order added
+    assert_eq!(
+        client.get("/steps").send().text(),
+        "a,m,b,router,router armor,handler,router armor after,router after,b after,m after,a after"
+    );
+    // the middleware wraps the handler call, so it can call it again
+    assert_eq!(
+        client.get("/flaky").send().text(),
+        "a,m,b,router,router armor,try,router,router armor,try,handler,router armor after,router after,b after,m after,a after"
+    );
+}
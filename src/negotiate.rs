@@ -0,0 +1,226 @@
+use headers::HeaderMap;
+
+// One item of an `Accept` like header, `text/html;level=1;q=0.8`.
+struct Item {
+    range: String,
+    params: Vec<(String, String)>,
+    q: f32,
+}
+
+// the items of all the values of header `name`, items with a malformed
+// q value are dropped
+fn items(headers: &HeaderMap, name: &str) -> Option<Vec<Item>> {
+    let values = headers.get_raw(name)?;
+    let mut items = Vec::new();
+
+    for value in values.iter() {
+        let value = String::from_utf8_lossy(value);
+        for item in value.split(',') {
+            let mut parts = item.split(';').map(|part| part.trim());
+            let range = match parts.next() {
+                Some(range) if !range.is_empty() => range.to_ascii_lowercase(),
+                _ => continue,
+            };
+
+            let mut q = Some(1.0);
+            let mut params = Vec::new();
+            for param in parts {
+                let mut kv = param.splitn(2, '=');
+                let key = kv.next().unwrap_or("").trim().to_ascii_lowercase();
+                let value = kv.next().unwrap_or("").trim().trim_matches('"');
+                if key == "q" {
+                    q = value.parse::<f32>().ok().filter(|q| *q >= 0.0 && *q <= 1.0);
+                } else if !key.is_empty() {
+                    params.push((key, value.to_owned()));
+                }
+            }
+
+            if let Some(q) = q {
+                items.push(Item { range, params, q });
+            }
+        }
+    }
+    Some(items)
+}
+
+// the best of `offers`: the quality of an offer is the one of the most
+// specific item matching it, the offer of the highest quality wins, ties
+// go to the first offered; offers no item matches get `unmatched`
+fn best<'o, M>(
+    items: Option<Vec<Item>>,
+    offers: &[&'o str],
+    matches: M,
+    unmatched: &Fn(&str) -> f32,
+) -> Option<&'o str>
+where
+    M: Fn(&Item, &str) -> Option<usize>,
+{
+    let items = match items {
+        Some(items) => items,
+        // no header, anything is acceptable
+        None => return offers.first().cloned(),
+    };
+
+    let mut chosen = None;
+    let mut chosen_q = 0.0;
+    for offer in offers.iter() {
+        let q = items
+            .iter()
+            .filter_map(|item| matches(item, offer).map(|specificity| (specificity, item.q)))
+            .fold(
+                None,
+                |most: Option<(usize, f32)>, (specificity, q)| match most {
+                    Some((s, _)) if s >= specificity => most,
+                    _ => Some((specificity, q)),
+                },
+            )
+            .map(|(_, q)| q)
+            .unwrap_or_else(|| unmatched(offer));
+
+        if q > chosen_q {
+            chosen = Some(*offer);
+            chosen_q = q;
+        }
+    }
+    chosen
+}
+
+// `*/*` < `type/*` < `type/subtype` < `type/subtype;param=value`
+fn media_matches(item: &Item, offer: &str) -> Option<usize> {
+    let mut offer_parts = offer.split(';').map(|part| part.trim());
+    let offer_type = offer_parts.next().unwrap_or("").to_ascii_lowercase();
+    let offer_params: Vec<String> = offer_parts
+        .map(|param| param.replace(' ', "").to_ascii_lowercase())
+        .collect();
+
+    let (range_type, range_sub) = split_media(&item.range);
+    let (offer_type, offer_sub) = split_media(&offer_type);
+
+    if range_type == "*" && range_sub == "*" {
+        Some(0)
+    } else if range_type != offer_type {
+        None
+    } else if range_sub == "*" {
+        Some(1)
+    } else if range_sub != offer_sub {
+        None
+    } else if item.params.is_empty() {
+        Some(2)
+    } else if item.params.iter().all(|&(ref key, ref value)| {
+        offer_params.contains(&format!("{}={}", key, value.to_ascii_lowercase()))
+    }) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+fn split_media(media: &str) -> (&str, &str) {
+    let mut parts = media.splitn(2, '/');
+    (parts.next().unwrap_or(""), parts.next().unwrap_or(""))
+}
+
+// `*` matches any tag, `en` matches `en` and `en-US`, longer ranges
+// are more specific
+fn language_matches(item: &Item, offer: &str) -> Option<usize> {
+    let offer = offer.to_ascii_lowercase();
+    if item.range == "*" {
+        Some(0)
+    } else if offer == item.range
+        || (offer.starts_with(&item.range) && offer[item.range.len()..].starts_with('-'))
+    {
+        Some(item.range.len())
+    } else {
+        None
+    }
+}
+
+fn encoding_matches(item: &Item, offer: &str) -> Option<usize> {
+    if item.range == "*" {
+        Some(0)
+    } else if item.range.eq_ignore_ascii_case(offer) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// the best of the media types in `offers` by the `Accept` header
+pub fn media_type<'o>(headers: &HeaderMap, offers: &[&'o str]) -> Option<&'o str> {
+    best(items(headers, "Accept"), offers, media_matches, &|_| 0.0)
+}
+
+/// the best of the language tags in `offers` by the `Accept-Language` header
+pub fn language<'o>(headers: &HeaderMap, offers: &[&'o str]) -> Option<&'o str> {
+    best(
+        items(headers, "Accept-Language"),
+        offers,
+        language_matches,
+        &|_| 0.0,
+    )
+}
+
+/// the best of the content codings in `offers` by the `Accept-Encoding`
+/// header, `identity` is acceptable unless refused explicitly
+pub fn encoding<'o>(headers: &HeaderMap, offers: &[&'o str]) -> Option<&'o str> {
+    best(
+        items(headers, "Accept-Encoding"),
+        offers,
+        encoding_matches,
+        &|offer| {
+            if offer.eq_ignore_ascii_case("identity") {
+                // below any explicit preference
+                0.001
+            } else {
+                0.0
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+fn headers(name: &str, value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.set_raw(name, vec![value.as_bytes().to_vec()]);
+    headers
+}
+
+#[test]
+fn negotiate_media_type() {
+    let offers = ["application/json", "text/html"];
+
+    let accept = headers("Accept", "text/html,application/xhtml+xml,*/*;q=0.8");
+    assert_eq!(media_type(&accept, &offers), Some("text/html"));
+
+    let accept = headers("Accept", "text/*;q=0.5, application/json");
+    assert_eq!(media_type(&accept, &offers), Some("application/json"));
+
+    // the most specific range decides
+    let accept = headers("Accept", "*/*, application/json;q=0");
+    assert_eq!(media_type(&accept, &offers), Some("text/html"));
+
+    let accept = headers("Accept", "image/png");
+    assert_eq!(media_type(&accept, &offers), None);
+
+    assert_eq!(
+        media_type(&HeaderMap::new(), &offers),
+        Some("application/json")
+    );
+}
+
+#[test]
+fn negotiate_language_and_encoding() {
+    let accept = headers("Accept-Language", "fr-CH, fr;q=0.9, en;q=0.8, *;q=0.5");
+    assert_eq!(language(&accept, &["en-US", "fr"]), Some("fr"));
+    assert_eq!(language(&accept, &["de", "en-GB"]), Some("en-GB"));
+
+    let accept = headers("Accept-Encoding", "gzip;q=0.5, br");
+    assert_eq!(encoding(&accept, &["gzip", "br", "identity"]), Some("br"));
+    assert_eq!(
+        encoding(&accept, &["deflate", "identity"]),
+        Some("identity")
+    );
+
+    let accept = headers("Accept-Encoding", "gzip, identity;q=0");
+    assert_eq!(encoding(&accept, &["identity"]), None);
+}
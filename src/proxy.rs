@@ -0,0 +1,313 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use headers::HeaderMap;
+
+/// A block of ip addresses, such as `10.0.0.0/8` or `fd00::/8`,
+/// a single address is a block of its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// whether `ip` is in the block
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::max_value()
+                    .checked_shl(32 - self.prefix as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::max_value()
+                    .checked_shl(128 - self.prefix as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Cidr, String> {
+        let invalid = || format!("invalid cidr `{}`", s);
+        let mut parts = s.trim().splitn(2, '/');
+        let addr: IpAddr = parts
+            .next()
+            .and_then(|addr| addr.parse().ok())
+            .ok_or_else(invalid)?;
+        let addr = canonical(addr);
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match parts.next() {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|&prefix| prefix <= max)
+                .ok_or_else(invalid)?,
+            None => max,
+        };
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+// ipv4 mapped ipv6 addresses, `::ffff:10.0.0.1`, are taken as ipv4
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => ip,
+        },
+        ip => ip,
+    }
+}
+
+/// The proxies whose forwarding headers are trusted
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    cidrs: Vec<Cidr>,
+}
+
+impl TrustedProxies {
+    pub fn new() -> TrustedProxies {
+        TrustedProxies { cidrs: Vec::new() }
+    }
+
+    pub fn push(&mut self, cidr: Cidr) {
+        self.cidrs.push(cidr);
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// the client address: the hops forwarded by trusted proxies are walked
+    /// from the nearest, the first one which is not a trusted proxy is the
+    /// client, `Forwarded` is preferred to `X-Forwarded-For`, `X-Real-IP`
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = canonical(peer);
+        if !self.contains(client) {
+            return client;
+        }
+
+        let hops = if headers.contains("Forwarded") {
+            forwarded_params(headers, "for")
+                .into_iter()
+                .flatten()
+                .collect()
+        } else if headers.contains("X-Forwarded-For") {
+            list_items(headers, "X-Forwarded-For")
+        } else {
+            list_items(headers, "X-Real-IP")
+        };
+
+        for hop in hops.iter().rev() {
+            match parse_node(hop) {
+                Some(ip) => {
+                    client = ip;
+                    if !self.contains(ip) {
+                        break;
+                    }
+                }
+                // obfuscated or unknown, the hop before it is as far as we know
+                None => break,
+            }
+        }
+        client
+    }
+
+    /// the scheme the client used, when the peer is a trusted proxy
+    pub fn scheme(&self, peer: IpAddr, headers: &HeaderMap) -> Option<String> {
+        if !self.contains(peer) {
+            return None;
+        }
+        self.forwarded(headers, "proto", "X-Forwarded-Proto")
+            .map(|proto| proto.to_ascii_lowercase())
+    }
+
+    /// the host the client asked for, when the peer is a trusted proxy
+    pub fn host(&self, peer: IpAddr, headers: &HeaderMap) -> Option<String> {
+        if !self.contains(peer) {
+            return None;
+        }
+        self.forwarded(headers, "host", "X-Forwarded-Host")
+    }
+
+    // the `key` parameter of `Forwarded`, or else header `name`, from the
+    // entries added by trusted proxies only: the client may send any value
+    // on its own, which the proxies append to, so the entries are walked
+    // from the nearest up to the client hop, the one of the client wins
+    fn forwarded(&self, headers: &HeaderMap, key: &str, name: &str) -> Option<String> {
+        if headers.contains("Forwarded") {
+            let fors = forwarded_params(headers, "for");
+            let values = forwarded_params(headers, key);
+            let first = values.len().saturating_sub(self.trusted_hops(&fors));
+            if let Some(value) = values[first..].iter().flatten().next() {
+                return Some(value.to_owned());
+            }
+        }
+
+        let fors: Vec<Option<String>> = list_items(headers, "X-Forwarded-For")
+            .into_iter()
+            .map(Some)
+            .collect();
+        let values = list_items(headers, name);
+        let first = values.len().saturating_sub(self.trusted_hops(&fors));
+        values.into_iter().nth(first)
+    }
+
+    // how many of the right-most forwarding entries were added by trusted
+    // proxies, the nearest one always is, then one for each trusted hop,
+    // up to and with the client hop
+    fn trusted_hops(&self, fors: &[Option<String>]) -> usize {
+        let mut hops = 0;
+        for hop in fors.iter().rev() {
+            hops += 1;
+            match hop.as_ref().and_then(|hop| parse_node(hop)) {
+                Some(ip) if self.contains(ip) => {}
+                _ => break,
+            }
+        }
+        hops.max(1)
+    }
+}
+
+// the comma separated items of all the values of header `name`
+fn list_items(headers: &HeaderMap, name: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    if let Some(values) = headers.get_raw(name) {
+        for value in values.iter() {
+            let value = String::from_utf8_lossy(value);
+            items.extend(
+                value
+                    .split(',')
+                    .map(|item| item.trim())
+                    .filter(|item| !item.is_empty())
+                    .map(|item| item.to_owned()),
+            );
+        }
+    }
+    items
+}
+
+// the values of parameter `key` of the `Forwarded` elements, in order, one
+// for each element, e.g. `for=192.0.2.60;proto=https, for="[2001:db8::1]:4711"`
+fn forwarded_params(headers: &HeaderMap, key: &str) -> Vec<Option<String>> {
+    list_items(headers, "Forwarded")
+        .iter()
+        .map(|element| {
+            element.split(';').find_map(|pair| {
+                let mut kv = pair.trim().splitn(2, '=');
+                match (kv.next(), kv.next()) {
+                    (Some(k), Some(v)) if k.trim().eq_ignore_ascii_case(key) => {
+                        Some(v.trim().trim_matches('"').to_owned())
+                    }
+                    _ => None,
+                }
+            })
+        })
+        .collect()
+}
+
+// an address as forwarded, with or without port: `192.0.2.43:47011`,
+// `[2001:db8::17]:4711`, `2001:db8::17`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if node.starts_with('[') {
+        let end = node.find(']')?;
+        return node[1..end]
+            .parse::<Ipv6Addr>()
+            .ok()
+            .map(|ip| canonical(IpAddr::V6(ip)));
+    }
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(canonical(ip));
+    }
+    let mut parts = node.rsplitn(2, ':');
+    parts.next();
+    parts
+        .next()
+        .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+        .map(IpAddr::V4)
+}
+
+#[test]
+fn cidr_contains() {
+    let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+    assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+    assert!(cidr.contains("::ffff:10.1.2.3".parse().unwrap()));
+    assert!(!cidr.contains("11.0.0.1".parse().unwrap()));
+
+    let cidr: Cidr = "fd00::/8".parse().unwrap();
+    assert!(cidr.contains("fd12::1".parse().unwrap()));
+    assert!(!cidr.contains("10.0.0.1".parse().unwrap()));
+
+    let cidr: Cidr = "0.0.0.0/0".parse().unwrap();
+    assert!(cidr.contains("1.2.3.4".parse().unwrap()));
+
+    assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    assert!("localhost".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn forwarded_chain() {
+    let mut proxies = TrustedProxies::new();
+    proxies.push("10.0.0.0/8".parse().unwrap());
+    let lb: IpAddr = "10.0.0.1".parse().unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.set_raw(
+        "X-Forwarded-For",
+        vec![b"1.1.1.1, 2.2.2.2, 10.0.0.2".to_vec()],
+    );
+    headers.set_raw("X-Forwarded-Proto", vec![b"HTTPS".to_vec()]);
+    assert_eq!(
+        proxies.client_ip(lb, &headers),
+        "2.2.2.2".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(proxies.scheme(lb, &headers), Some("https".to_owned()));
+
+    // untrusted peers can not forward
+    let peer: IpAddr = "3.3.3.3".parse().unwrap();
+    assert_eq!(proxies.client_ip(peer, &headers), peer);
+    assert_eq!(proxies.scheme(peer, &headers), None);
+
+    let mut headers = HeaderMap::new();
+    headers.set_raw(
+        "Forwarded",
+        vec![b"for=\"[2001:db8::17]:4711\";proto=https;host=example.com, for=10.0.0.3".to_vec()],
+    );
+    assert_eq!(
+        proxies.client_ip(lb, &headers),
+        "2001:db8::17".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(proxies.host(lb, &headers), Some("example.com".to_owned()));
+}
+
+#[test]
+fn forwarded_spoofed() {
+    let mut proxies = TrustedProxies::new();
+    proxies.push("10.0.0.0/8".parse().unwrap());
+    let lb: IpAddr = "10.0.0.1".parse().unwrap();
+
+    // the client sent its own values, the proxy appended the real ones
+    let mut headers = HeaderMap::new();
+    headers.set_raw("X-Forwarded-For", vec![b"6.6.6.6, 2.2.2.2".to_vec()]);
+    headers.set_raw("X-Forwarded-Proto", vec![b"https, http".to_vec()]);
+    headers.set_raw("X-Forwarded-Host", vec![b"evil.com, example.com".to_vec()]);
+    assert_eq!(proxies.scheme(lb, &headers), Some("http".to_owned()));
+    assert_eq!(proxies.host(lb, &headers), Some("example.com".to_owned()));
+
+    let mut headers = HeaderMap::new();
+    headers.set_raw(
+        "Forwarded",
+        vec![b"for=6.6.6.6;proto=https;host=evil.com, for=2.2.2.2;proto=http;host=example.com, for=10.0.0.3".to_vec()],
+    );
+    assert_eq!(proxies.scheme(lb, &headers), Some("http".to_owned()));
+    assert_eq!(proxies.host(lb, &headers), Some("example.com".to_owned()));
+}
@@ -1,7 +1,9 @@
 pub mod nfa;
+pub mod tree;
 
 use self::nfa::CharacterClass;
 use self::nfa::NFA;
+use self::tree::Tree;
 use std::cmp::Ordering;
 use std::collections::btree_map;
 use std::collections::BTreeMap;
@@ -135,21 +137,154 @@ impl<T> Match<T> {
     }
 }
 
+/// Router matching with the compiled segment tree
 #[derive(Clone)]
 pub struct Router<T> {
-    nfa: NFA<Metadata>,
-    handlers: BTreeMap<usize, T>,
+    tree: Tree,
+    // the routes of each tree leaf, in the order added: routes of the same
+    // shape share a leaf, each with its own param names
+    leaves: Vec<Vec<usize>>,
+    // metadata and handler of each route
+    routes: Vec<(Metadata, Option<T>)>,
 }
 
 impl<T> Router<T> {
     pub fn new() -> Router<T> {
         Router {
+            tree: Tree::new(),
+            leaves: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, route: &str, dest: T) {
+        let index = self.add_route(route);
+        self.routes[index].1 = Some(dest);
+    }
+
+    /// get the destination of `route` to modify it, adding a default one first
+    /// if the route is new
+    pub fn entry(&mut self, route: &str) -> &mut T
+    where
+        T: Default,
+    {
+        let index = self.add_route(route);
+        self.routes[index].1.get_or_insert_with(T::default)
+    }
+
+    fn add_route(&mut self, mut route: &str) -> usize {
+        if route.len() != 0 && route.as_bytes()[0] == b'/' {
+            route = &route[1..];
+        }
+
+        let mut metadata = Metadata::new();
+        for segment in route.split('/') {
+            if segment.len() > 0 && segment.as_bytes()[0] == b':' {
+                metadata.dynamics += 1;
+                metadata.param_names.push(segment[1..].to_string());
+            } else if segment.len() > 0 && segment.as_bytes()[0] == b'*' {
+                metadata.stars += 1;
+                metadata.param_names.push(segment[1..].to_string());
+            } else {
+                metadata.statics += 1;
+            }
+        }
+
+        let leaf = self.tree.add(route);
+        if leaf == self.leaves.len() {
+            self.leaves.push(Vec::new());
+        }
+        let routes = &self.routes;
+        let same = self.leaves[leaf]
+            .iter()
+            .find(|&&index| routes[index].0.param_names == metadata.param_names);
+        match same {
+            Some(&index) => index,
+            None => {
+                let index = self.routes.len();
+                self.routes.push((metadata, None));
+                self.leaves[leaf].push(index);
+                index
+            }
+        }
+    }
+
+    pub fn recognize<'a>(&'a self, path: &str) -> Result<Match<&'a T>, String> {
+        match self.recognize_all(path).next() {
+            Some(matched) => Ok(matched),
+            None => Err(format!("Couldn't recognize {}", path)),
+        }
+    }
+
+    /// all the routes matching `path`, in priority order, ties are won by
+    /// the route with static segments earlier in the path
+    pub fn recognize_all<'a, 'p>(&'a self, path: &'p str) -> impl Iterator<Item = Match<&'a T>> + 'p
+    where
+        'a: 'p,
+    {
+        let path = if path.len() != 0 && path.as_bytes()[0] == b'/' {
+            &path[1..]
+        } else {
+            path
+        };
+
+        let mut matches: Vec<(usize, Vec<(usize, usize)>)> = Vec::new();
+        for tree_match in self.tree.recognize(path) {
+            for &index in self.leaves[tree_match.leaf].iter() {
+                matches.push((index, tree_match.captures.clone()));
+            }
+        }
+        // stable, so the first match wins among equals
+        matches.sort_by(|x, y| self.routes[y.0].0.cmp(&self.routes[x.0].0));
+
+        // params are only collected for the matches actually looked at
+        matches.into_iter().filter_map(move |(index, captures)| {
+            let (ref metadata, ref handler) = self.routes[index];
+            handler.as_ref().map(|handler| {
+                let mut map = Params::new();
+                for (i, &(begin, end)) in captures.iter().enumerate() {
+                    map.insert(
+                        metadata.param_names[i].clone(),
+                        path[begin..end].to_string(),
+                    );
+                }
+                Match::new(handler, map)
+            })
+        })
+    }
+}
+
+/// Router simulating the NFA, kept to compare with the compiled `Router`
+#[derive(Clone)]
+pub struct NfaRouter<T> {
+    nfa: NFA<Metadata>,
+    handlers: BTreeMap<usize, T>,
+}
+
+impl<T> NfaRouter<T> {
+    pub fn new() -> NfaRouter<T> {
+        NfaRouter {
             nfa: NFA::new(),
             handlers: BTreeMap::new(),
         }
     }
 
-    pub fn add(&mut self, mut route: &str, dest: T) {
+    pub fn add(&mut self, route: &str, dest: T) {
+        let state = self.add_route(route);
+        self.handlers.insert(state, dest);
+    }
+
+    /// get the destination of `route` to modify it, adding a default one first
+    /// if the route is new
+    pub fn entry(&mut self, route: &str) -> &mut T
+    where
+        T: Default,
+    {
+        let state = self.add_route(route);
+        self.handlers.entry(state).or_insert_with(T::default)
+    }
+
+    fn add_route(&mut self, mut route: &str) -> usize {
         if route.len() != 0 && route.as_bytes()[0] == b'/' {
             route = &route[1..];
         }
@@ -179,19 +314,28 @@ impl<T> Router<T> {
 
         nfa.acceptance(state);
         nfa.metadata(state, metadata);
-        self.handlers.insert(state, dest);
+        state
     }
 
-    pub fn recognize<'a>(&'a self, mut path: &str) -> Result<Match<&'a T>, String> {
+    pub fn recognize<'a>(&'a self, path: &str) -> Result<Match<&'a T>, String> {
+        match self.recognize_all(path).into_iter().next() {
+            Some(matched) => Ok(matched),
+            None => Err(format!("Couldn't recognize {}", path)),
+        }
+    }
+
+    /// all the routes matching `path`, in priority order
+    pub fn recognize_all<'a>(&'a self, mut path: &str) -> Vec<Match<&'a T>> {
         if path.len() != 0 && path.as_bytes()[0] == b'/' {
             path = &path[1..];
         }
 
         let nfa = &self.nfa;
-        let result = nfa.process(path, |index| nfa.get(index).metadata.as_ref().unwrap());
+        let results = nfa.process_all(path, |index| nfa.get(index).metadata.as_ref().unwrap());
 
-        match result {
-            Ok(nfa_match) => {
+        results
+            .into_iter()
+            .map(|nfa_match| {
                 let mut map = Params::new();
                 let state = &nfa.get(nfa_match.state);
                 let metadata = state.metadata.as_ref().unwrap();
@@ -202,10 +346,9 @@ impl<T> Router<T> {
                 }
 
                 let handler = self.handlers.get(&nfa_match.state).unwrap();
-                Ok(Match::new(handler, map))
-            }
-            Err(str) => Err(str),
-        }
+                Match::new(handler, map)
+            })
+            .collect()
     }
 }
 
@@ -342,6 +485,103 @@ fn star() {
     assert_eq!(m.params, params("foo", "bar/foo"));
 }
 
+#[test]
+fn recognize_all_in_priority_order() {
+    let mut router = Router::new();
+
+    router.add("/posts/:id", "id".to_string());
+    router.add("/posts/new", "new".to_string());
+    router.add("/*path", "path".to_string());
+
+    let handlers: Vec<String> = router
+        .recognize_all("/posts/new")
+        .map(|m| m.handler.clone())
+        .collect();
+    assert_eq!(handlers, vec!["new", "id", "path"]);
+}
+
+#[test]
+fn same_shape_param_names() {
+    let mut router = Router::new();
+
+    router.add("/users/:id", "id".to_string());
+    router.add("/users/:name", "name".to_string());
+
+    let matches: Vec<(String, Params)> = router
+        .recognize_all("/users/7")
+        .map(|m| (m.handler.clone(), m.params))
+        .collect();
+    assert_eq!(
+        matches,
+        vec![
+            ("id".to_string(), params("id", "7")),
+            ("name".to_string(), params("name", "7")),
+        ]
+    );
+
+    // the same route again replaces its handler
+    router.add("/users/:id", "id2".to_string());
+    let m = router.recognize("/users/7").unwrap();
+    assert_eq!(*m.handler, "id2".to_string());
+    assert_eq!(m.params, params("id", "7"));
+}
+
+#[test]
+fn entry_router() {
+    let mut router: Router<Vec<u32>> = Router::new();
+
+    router.entry("/posts/:id").push(1);
+    router.entry("/posts/:id").push(2);
+
+    assert_eq!(*router.recognize("/posts/1").unwrap().handler, vec![1, 2]);
+}
+
+#[test]
+fn tree_agrees_with_nfa() {
+    let routes = [
+        "/",
+        "/posts",
+        "/posts/new",
+        "/posts/:id",
+        "/posts/:id/edit",
+        "/posts/:post_id/comments/:id",
+        "/users/:id",
+        "/static/*path",
+        "*catchall",
+    ];
+    let paths = [
+        "/",
+        "/posts",
+        "/posts/new",
+        "/posts/12",
+        "/posts/12/edit",
+        "/posts/12/comments/3",
+        "/posts/",
+        "/users/tom",
+        "/static/css/site.css",
+        "/nothing/here",
+    ];
+
+    let mut router = Router::new();
+    let mut nfa_router = NfaRouter::new();
+    for route in routes.iter() {
+        router.add(route, route.to_string());
+        nfa_router.add(route, route.to_string());
+    }
+
+    for path in paths.iter() {
+        let m = router
+            .recognize(path)
+            .ok()
+            .map(|m| (m.handler.clone(), m.params));
+        let n = nfa_router
+            .recognize(path)
+            .ok()
+            .map(|m| (m.handler.clone(), m.params));
+        assert_eq!(m, n, "{}", path);
+    }
+}
+
 #[allow(dead_code)]
 fn params(key: &str, val: &str) -> Params {
     let mut map = Params::new();
@@ -225,7 +225,20 @@ impl<T> NFA<T> {
         }
     }
 
-    pub fn process<'a, I, F>(&self, string: &'a str, mut ord: F) -> Result<Match<'a>, String>
+    pub fn process<'a, I, F>(&self, string: &'a str, ord: F) -> Result<Match<'a>, String>
+    where
+        I: Ord,
+        F: FnMut(usize) -> I,
+    {
+        match self.process_all(string, ord).into_iter().next() {
+            Some(matched) => Ok(matched),
+            None => Err(format!("Couldn't process {}", string)),
+        }
+    }
+
+    /// all the acceptance states reached, the highest ordered first,
+    /// each state is only reported once
+    pub fn process_all<'a, I, F>(&self, string: &'a str, mut ord: F) -> Vec<Match<'a>>
     where
         I: Ord,
         F: FnMut(usize) -> I,
@@ -236,44 +249,32 @@ impl<T> NFA<T> {
             let next_threads = self.process_char(threads, char, i);
 
             if next_threads.is_empty() {
-                return Err(format!("Couldn't process {}", string));
+                return Vec::new();
             }
 
             threads = next_threads;
         }
 
-        let returned = threads
+        let mut returned: Vec<(I, Thread)> = threads
             .into_iter()
-            .filter(|thread| self.get(thread.state).acceptance);
-
-        let thread = returned
-            .fold(None, |prev, y| {
-                let y_v = ord(y.state);
-                match prev {
-                    None => Some((y_v, y)),
-                    Some((x_v, x)) => {
-                        if x_v < y_v {
-                            Some((y_v, y))
-                        } else {
-                            Some((x_v, x))
-                        }
-                    }
-                }
-            })
-            .map(|p| p.1);
+            .filter(|thread| self.get(thread.state).acceptance)
+            .map(|thread| (ord(thread.state), thread))
+            .collect();
+        // stable, so the first thread wins among equals
+        returned.sort_by(|x, y| y.0.cmp(&x.0));
 
-        match thread {
-            None => Err("The string was exhausted before reaching an \
-                             acceptance state"
-                .to_string()),
-            Some(mut thread) => {
+        let mut seen = HashSet::new();
+        returned
+            .into_iter()
+            .filter(|&(_, ref thread)| seen.insert(thread.state))
+            .map(|(_, mut thread)| {
                 if thread.capture_begin.is_some() {
                     thread.end_capture(string.len());
                 }
                 let state = self.get(thread.state);
-                Ok(Match::new(state.index, thread.extract(string)))
-            }
-        }
+                Match::new(state.index, thread.extract(string))
+            })
+            .collect()
     }
 
     #[inline]
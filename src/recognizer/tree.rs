@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+/// A segment radix tree, the compiled form of a route set.
+///
+/// Routes and paths are split on `/` once, and matching walks the tree
+/// segment by segment instead of simulating the NFA character by character.
+/// A segment starting with `:` matches one non empty segment, a segment
+/// starting with `*` matches one or more segments, others match literally.
+#[derive(Clone)]
+pub struct Tree {
+    root: Node,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct Node {
+    statics: HashMap<String, Node>,
+    dynamic: Option<Box<Node>>,
+    star: Option<Box<Node>>,
+    leaf: Option<usize>,
+}
+
+impl Node {
+    fn new() -> Node {
+        Node {
+            statics: HashMap::new(),
+            dynamic: None,
+            star: None,
+            leaf: None,
+        }
+    }
+}
+
+/// A route matched by the tree, with the byte ranges of its captures.
+pub struct Match {
+    pub leaf: usize,
+    pub captures: Vec<(usize, usize)>,
+}
+
+impl Tree {
+    pub fn new() -> Tree {
+        Tree {
+            root: Node::new(),
+            len: 0,
+        }
+    }
+
+    /// add `route` (without the leading `/`), return its leaf index;
+    /// routes with the same shape share one leaf
+    pub fn add(&mut self, route: &str) -> usize {
+        let mut node = &mut self.root;
+
+        for segment in route.split('/') {
+            node = if segment.starts_with(':') {
+                &mut **node.dynamic.get_or_insert_with(|| Box::new(Node::new()))
+            } else if segment.starts_with('*') {
+                &mut **node.star.get_or_insert_with(|| Box::new(Node::new()))
+            } else {
+                node.statics
+                    .entry(segment.to_owned())
+                    .or_insert_with(Node::new)
+            };
+        }
+
+        match node.leaf {
+            Some(leaf) => leaf,
+            None => {
+                let leaf = self.len;
+                node.leaf = Some(leaf);
+                self.len += 1;
+                leaf
+            }
+        }
+    }
+
+    /// all the routes matching `path` (without the leading `/`), static
+    /// children are tried first, then dynamic ones, then stars
+    pub fn recognize(&self, path: &str) -> Vec<Match> {
+        let mut segments = Vec::new();
+        let mut begin = 0;
+        for (i, byte) in path.bytes().enumerate() {
+            if byte == b'/' {
+                segments.push((begin, i));
+                begin = i + 1;
+            }
+        }
+        segments.push((begin, path.len()));
+
+        let mut matches = Vec::new();
+        let mut captures = Vec::new();
+        walk(&self.root, path, &segments, &mut captures, &mut matches);
+        matches
+    }
+}
+
+fn walk(
+    node: &Node,
+    path: &str,
+    segments: &[(usize, usize)],
+    captures: &mut Vec<(usize, usize)>,
+    matches: &mut Vec<Match>,
+) {
+    let (begin, end) = match segments.first() {
+        Some(&segment) => segment,
+        None => {
+            if let Some(leaf) = node.leaf {
+                matches.push(Match {
+                    leaf,
+                    captures: captures.clone(),
+                });
+            }
+            return;
+        }
+    };
+
+    if let Some(child) = node.statics.get(&path[begin..end]) {
+        walk(child, path, &segments[1..], captures, matches);
+    }
+
+    if let Some(ref child) = node.dynamic {
+        if end > begin {
+            captures.push((begin, end));
+            walk(child, path, &segments[1..], captures, matches);
+            captures.pop();
+        }
+    }
+
+    if let Some(ref child) = node.star {
+        // a star may swallow any number of whole segments, but not nothing
+        for (i, &(_, end)) in segments.iter().enumerate() {
+            if end > begin {
+                captures.push((begin, end));
+                walk(child, path, &segments[i + 1..], captures, matches);
+                captures.pop();
+            }
+        }
+    }
+}
+
+#[test]
+fn tree_shapes() {
+    let mut tree = Tree::new();
+
+    let a = tree.add("posts/:id");
+    let b = tree.add("posts/:post_id");
+    let c = tree.add("posts/new");
+
+    assert_eq!(a, b);
+    assert!(a != c);
+}
+
+#[test]
+fn tree_captures() {
+    let mut tree = Tree::new();
+    let leaf = tree.add("p/:id/c/*rest");
+    let path = "p/123/c/4/5";
+
+    let matches = tree.recognize(path);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].leaf, leaf);
+
+    let captures: Vec<&str> = matches[0]
+        .captures
+        .iter()
+        .map(|&(begin, end)| &path[begin..end])
+        .collect();
+    assert_eq!(captures, vec!["123", "4/5"]);
+}
+
+#[test]
+fn tree_star_in_middle() {
+    let mut tree = Tree::new();
+    tree.add("*dir/index");
+
+    assert_eq!(tree.recognize("a/b/index").len(), 1);
+    assert_eq!(tree.recognize("index").len(), 0);
+    assert_eq!(tree.recognize("/index").len(), 0);
+}
@@ -1,96 +1,461 @@
+use std::any::{type_name, Any};
 use std::io::Read;
-use std::net::SocketAddr;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
-use hyper::header::Headers;
-use hyper::method::Method;
+use hyper::header::ContentLength;
 use hyper::server::request::Request as HyperRequest;
-use hyper::uri::RequestUri;
-use hyper::version::HttpVersion;
-use typemap::TypeMap;
-
-/// Sapper request struct
-pub struct SapperRequest<'a, 'b: 'a> {
-    raw_req: Box<HyperRequest<'a, 'b>>,
-    ext: TypeMap,
+use typemap::{Key, ShareMap, TypeMap};
+
+use app::{Error, MountPrefix, Result};
+use cookie::CookieJar;
+use decompress;
+use headers::HeaderMap;
+use http::{Method, Version};
+use negotiate;
+use proxy::TrustedProxies;
+use response::SapperResponse;
+use state::StateMap;
+use uri::Uri;
+
+/// Sapper request struct, owns all its parts, so it can outlive
+/// the connection it came from
+pub struct SapperRequest {
+    remote_addr: SocketAddr,
+    method: Method,
+    version: Version,
+    headers: HeaderMap,
+    uri: Uri,
+    body: Option<Vec<u8>>,
+    // proxies trusted to tell the client address, scheme and host
+    proxies: Arc<TrustedProxies>,
+    ext: ShareMap,
+    // shared states visible to this request, the innermost scope last
+    states: Vec<Arc<StateMap>>,
+    // the response an error is answered with, as the armors made it so far
+    error_response: Option<(Error, SapperResponse)>,
+    // false when the body was not read to its end, over the limit or broken
+    body_complete: bool,
 }
 
-impl<'a, 'b: 'a> SapperRequest<'a, 'b> {
-    /// constructor
-    pub fn new(req: Box<HyperRequest<'a, 'b>>) -> SapperRequest<'a, 'b> {
+impl SapperRequest {
+    // convert a hyper request, its body is read here at once, up to one
+    // byte over `limit`, so `decode_body` can tell it is too large
+    pub(crate) fn from_hyper(req: HyperRequest, limit: usize) -> SapperRequest {
+        let headers = HeaderMap::from(&req.headers);
+        let (remote_addr, method, _, uri, version, reader) = req.deconstruct();
+
+        let mut body = Vec::new();
+        let mut body_complete = true;
+        if let Err(err) = reader.take(limit as u64 + 1).read_to_end(&mut body) {
+            error!("request body reading error: {}", err);
+            body.clear();
+            body_complete = false;
+        }
+        if body.len() > limit {
+            body_complete = false;
+        }
+
         SapperRequest {
-            raw_req: req,
-            ext: TypeMap::new(),
+            remote_addr,
+            method: method.into(),
+            version: version.into(),
+            headers,
+            uri: uri.into(),
+            body: if body.is_empty() { None } else { Some(body) },
+            proxies: Arc::new(TrustedProxies::new()),
+            ext: TypeMap::custom(),
+            states: Vec::new(),
+            error_response: None,
+            body_complete,
         }
     }
 
+    /// start building a request without a connection, to test handlers
+    /// and modules directly
+    pub fn builder() -> SapperRequestBuilder {
+        SapperRequestBuilder::new()
+    }
+
     /// get remote ip address
     pub fn remote_addr(&self) -> SocketAddr {
-        self.raw_req.remote_addr
+        self.remote_addr
+    }
+
+    /// get the ip address of the client, as told by the trusted proxies
+    /// the request went through, see `SapperApp::trust_proxies`
+    pub fn client_ip(&self) -> IpAddr {
+        self.proxies.client_ip(self.remote_addr.ip(), &self.headers)
+    }
+
+    /// get the scheme the client used, as told by a trusted proxy,
+    /// or else of an absolute form target, or else `http`
+    pub fn scheme(&self) -> String {
+        self.proxies
+            .scheme(self.remote_addr.ip(), &self.headers)
+            .or_else(|| self.uri.scheme().map(|scheme| scheme.to_owned()))
+            .unwrap_or_else(|| "http".to_owned())
+    }
+
+    /// get the host the client asked for, as told by a trusted proxy,
+    /// or else of an absolute form target, or else the `Host` header
+    pub fn host(&self) -> Option<String> {
+        self.proxies
+            .host(self.remote_addr.ip(), &self.headers)
+            .or_else(|| {
+                // of the target, without the user info it may carry
+                self.uri
+                    .host()
+                    .map(|host| match self.uri.port() {
+                        Some(port) => format!("{}:{}", host, port),
+                        None => host.to_owned(),
+                    })
+                    .or_else(|| self.headers.get_str("Host").map(|host| host.to_owned()))
+            })
+    }
+
+    pub(crate) fn set_proxies(&mut self, proxies: Arc<TrustedProxies>) {
+        self.proxies = proxies;
     }
 
     /// get http method
     pub fn method(&self) -> &Method {
-        &self.raw_req.method
+        &self.method
     }
 
     /// get http version
-    pub fn version(&self) -> HttpVersion {
-        self.raw_req.version
+    pub fn version(&self) -> Version {
+        self.version
     }
 
     /// get http headers referrence
-    pub fn headers(&self) -> &Headers {
-        &self.raw_req.headers
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// get http headers mut referrence
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
     }
 
-    /// get request path, and query parts
-    // TODO: optimize to (&str, Option<&str>)
-    // uri() -> (path, query)
+    /// get the cookies the client sent
+    pub fn cookies(&self) -> CookieJar {
+        let values = self.headers.get_raw("Cookie").unwrap_or(&[]);
+        CookieJar::parse(values.iter().map(|value| &value[..]))
+    }
+
+    /// get the request target, as sent
+    pub fn target(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// get request path, and query parts, prefer `path()` and `query()`
+    /// which do not allocate
     pub fn uri(&self) -> (String, Option<String>) {
-        match self.raw_req.uri {
-            RequestUri::AbsolutePath(ref uri) => {
-                let pathvec: Vec<&str> = uri[..].split('?').collect();
-                let path = pathvec[0].to_owned();
-                let mut query = None;
-
-                // if has query_string
-                if pathvec.len() > 1 {
-                    query = Some(pathvec[1].to_owned());
-                }
-
-                (path, query)
-            }
-            //_ => unreachable!()
-            _ => ("".to_owned(), None),
+        (
+            self.path().to_owned(),
+            self.query().map(|query| query.to_owned()),
+        )
+    }
+
+    /// get the normalized request path, also of absolute form targets
+    pub fn path(&self) -> &str {
+        self.uri.path()
+    }
+
+    /// get the query string, without the `?`
+    pub fn query(&self) -> Option<&str> {
+        self.uri.query()
+    }
+
+    /// get the full url of the request, with the `scheme()` and `host()`
+    pub fn url(&self) -> String {
+        let scheme = self.scheme();
+        let host = self.host().unwrap_or_default();
+
+        let mut url = format!("{}://{}{}", scheme, host, self.path());
+        if let Some(query) = self.query() {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+
+    /// build a url under the prefix the handling module was mounted at,
+    /// and the prefixes of the groups and nested modules of the route
+    pub fn url_for(&self, path: &str) -> String {
+        match self.ext.get::<MountPrefix>() {
+            Some(prefix) => prefix.clone() + path,
+            None => path.to_owned(),
         }
     }
 
-    /// get the raw body vec of this request
-    // here, we read it all for simplify upload biz
-    pub fn body(&mut self) -> Option<Vec<u8>> {
-        let mut body_vec: Vec<u8> = vec![];
-        match self.raw_req.read_to_end(&mut body_vec) {
-            Ok(n) => {
-                if n > 0 {
-                    Some(body_vec)
-                } else {
-                    None
-                }
+    // check the body is within `limit`, and decode it by its
+    // `Content-Encoding`, so body parsers get the plain body
+    pub(crate) fn decode_body(&mut self, limit: usize) -> Result<()> {
+        let body = match self.body.take() {
+            Some(body) => body,
+            None => return Ok(()),
+        };
+        if body.len() > limit {
+            return Err(Error::PayloadTooLarge);
+        }
+
+        let codings = match self.headers.get_str("Content-Encoding") {
+            Some(codings) => codings.to_owned(),
+            None => {
+                self.body = Some(body);
+                return Ok(());
             }
-            Err(_) => {
-                println!("request body reading error!");
-                None
+        };
+        let body = decompress::decode(&codings, body, limit)?;
+        self.headers.remove_raw("Content-Encoding");
+        self.headers
+            .set_raw("Content-Length", vec![body.len().to_string().into_bytes()]);
+        self.body = if body.is_empty() { None } else { Some(body) };
+        Ok(())
+    }
+
+    // the response `err` is answered with, the one the `after` of inner
+    // armors worked on when they saw the same error
+    pub(crate) fn error_response(&mut self, err: &Error) -> SapperResponse {
+        match self.error_response.take() {
+            Some((ref seen, ref mut response)) if seen == err => {
+                mem::replace(response, SapperResponse::new())
             }
+            _ => SapperResponse::from(err.clone()),
         }
     }
 
+    pub(crate) fn set_error_response(&mut self, err: Error, response: SapperResponse) {
+        self.error_response = Some((err, response));
+    }
+
+    // whether the body was read to its end, else the rest of it is still
+    // on the connection
+    pub(crate) fn body_complete(&self) -> bool {
+        self.body_complete
+    }
+
+    /// get the raw body vec of this request, a copy, so body parsers,
+    /// armors and the handler all can read it
+    pub fn body(&self) -> Option<Vec<u8>> {
+        self.body.clone()
+    }
+
+    /// get the raw body vec of this request without copying it, it is taken
+    /// out, so the later readers get `None`
+    pub fn take_body(&mut self) -> Option<Vec<u8>> {
+        self.body.take()
+    }
+
+    /// get the best of the media types in `offers` for the client, by the
+    /// `Accept` header and its q-values, e.g.
+    /// `req.negotiate(&["application/json", "text/html"])`,
+    /// `NotAcceptable` when the client accepts none of them
+    pub fn negotiate<'o>(&self, offers: &[&'o str]) -> Result<&'o str> {
+        negotiate::media_type(&self.headers, offers).ok_or(Error::NotAcceptable)
+    }
+
+    /// like `negotiate`, of language tags by the `Accept-Language` header
+    pub fn negotiate_language<'o>(&self, offers: &[&'o str]) -> Result<&'o str> {
+        negotiate::language(&self.headers, offers).ok_or(Error::NotAcceptable)
+    }
+
+    /// like `negotiate`, of content codings by the `Accept-Encoding` header
+    pub fn negotiate_encoding<'o>(&self, offers: &[&'o str]) -> Result<&'o str> {
+        negotiate::encoding(&self.headers, offers).ok_or(Error::NotAcceptable)
+    }
+
+    /// get the shared state of type `T`, registered by `SapperApp::manage`
+    /// or by `SapperRouter::manage` for the routes of one module or group
+    pub fn state<T: Any + Send + Sync>(&self) -> Result<&T> {
+        self.states
+            .iter()
+            .rev()
+            .filter_map(|state| state.get::<T>())
+            .next()
+            .ok_or_else(|| {
+                Error::InternalServerError(format!(
+                    "no state of type `{}` is managed",
+                    type_name::<T>()
+                ))
+            })
+    }
+
+    pub(crate) fn add_state(&mut self, state: Arc<StateMap>) {
+        self.states.push(state);
+    }
+
     /// get request struct ext ref
-    pub fn ext(&self) -> &TypeMap {
+    pub fn ext(&self) -> &ShareMap {
         &self.ext
     }
 
     /// get request struct ext mut ref
-    pub fn ext_mut(&mut self) -> &mut TypeMap {
+    pub fn ext_mut(&mut self) -> &mut ShareMap {
         &mut self.ext
     }
 }
+
+/// Sapper request builder, makes requests without a connection,
+/// to test handlers and modules directly
+///
+/// ```ignore
+/// let mut req = SapperRequest::builder()
+///     .method(Method::Post)
+///     .uri("/foo?page=1")
+///     .header("Content-Type", "application/json")
+///     .body("{}")
+///     .ext::<PathParams>(params)
+///     .build();
+/// let res = Foo::create(&mut req);
+/// ```
+pub struct SapperRequestBuilder {
+    remote_addr: SocketAddr,
+    method: Method,
+    version: Version,
+    headers: HeaderMap,
+    uri: Uri,
+    body: Vec<u8>,
+    proxies: TrustedProxies,
+    ext: ShareMap,
+}
+
+impl SapperRequestBuilder {
+    /// constructor, a `GET /` request from localhost
+    pub fn new() -> SapperRequestBuilder {
+        SapperRequestBuilder {
+            remote_addr: "127.0.0.1:80".parse().unwrap(),
+            method: Method::Get,
+            version: Version::Http11,
+            headers: HeaderMap::new(),
+            uri: Uri::new("/"),
+            body: Vec::new(),
+            proxies: TrustedProxies::new(),
+            ext: TypeMap::custom(),
+        }
+    }
+
+    /// set http method
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// set the request target, such as `/foo?page=1`
+    pub fn uri(mut self, uri: &str) -> Self {
+        self.uri = Uri::new(uri);
+        self
+    }
+
+    /// set http version
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// set a header, replacing the values it had
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .set_raw(name.to_owned(), vec![value.as_bytes().to_vec()]);
+        self
+    }
+
+    /// set all the headers
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// set the body, with its `Content-Length` unless one is set already
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        if !self.headers.has::<ContentLength>() {
+            self.headers.set(ContentLength(self.body.len() as u64));
+        }
+        self
+    }
+
+    /// set remote ip address
+    pub fn remote_addr(mut self, addr: SocketAddr) -> Self {
+        self.remote_addr = addr;
+        self
+    }
+
+    /// trust the forwarding headers from the proxies in `cidr`,
+    /// such as `10.0.0.0/8`
+    pub fn trusted_proxy(mut self, cidr: &str) -> Self {
+        self.proxies
+            .push(cidr.parse().unwrap_or_else(|err| panic!("{}", err)));
+        self
+    }
+
+    /// pre-seed an ext entry, such as the `PathParams` a router would insert
+    pub fn ext<K: Key>(mut self, value: K::Value) -> Self
+    where
+        K::Value: Any + Send + Sync,
+    {
+        self.ext.insert::<K>(value);
+        self
+    }
+
+    /// build the request
+    pub fn build(self) -> SapperRequest {
+        SapperRequest {
+            remote_addr: self.remote_addr,
+            method: self.method,
+            version: self.version,
+            headers: self.headers,
+            uri: self.uri,
+            body: if self.body.is_empty() {
+                None
+            } else {
+                Some(self.body)
+            },
+            proxies: Arc::new(self.proxies),
+            ext: self.ext,
+            states: Vec::new(),
+            error_response: None,
+            body_complete: true,
+        }
+    }
+}
+
+#[test]
+fn build_request() {
+    use app::PathParams;
+    use recognizer::Params;
+
+    let mut params = Params::new();
+    params.insert("id".to_owned(), "7".to_owned());
+
+    let mut req = SapperRequest::builder()
+        .method(Method::Post)
+        .uri("/posts/7?draft=1")
+        .header("X-Token", "abc")
+        .header("Host", "example.com")
+        .body("hello")
+        .ext::<PathParams>(params)
+        .build();
+
+    assert_eq!(*req.method(), Method::Post);
+    assert_eq!(req.path(), "/posts/7");
+    assert_eq!(req.query(), Some("draft=1"));
+    assert_eq!(req.url(), "http://example.com/posts/7?draft=1");
+    assert_eq!(req.headers().get::<ContentLength>(), Some(ContentLength(5)));
+    assert_eq!(req.ext().get::<PathParams>().unwrap()["id"], "7");
+    assert_eq!(req.body(), Some(b"hello".to_vec()));
+    // read again, by the next reader
+    assert_eq!(req.body(), Some(b"hello".to_vec()));
+    assert_eq!(req.take_body(), Some(b"hello".to_vec()));
+    assert_eq!(req.body(), None);
+
+    // the user info of an absolute form target is not of the host
+    let req = SapperRequest::builder()
+        .uri("http://user:pw@example.com:8080/p")
+        .build();
+    assert_eq!(req.host(), Some("example.com:8080".to_owned()));
+    assert_eq!(req.url(), "http://example.com:8080/p");
+}
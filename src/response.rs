@@ -1,19 +1,31 @@
-use hyper::header::Headers;
+use std::io::Read;
+
+use headers::HeaderMap;
 use hyper::status::StatusCode;
 
+use app::{Error, Result};
+use cookie::CookieJar;
+use request::SapperRequest;
+
 /// Sapper response struct
 pub struct SapperResponse {
     status: StatusCode,
-    headers: Headers,
+    headers: HeaderMap,
     body: Option<Vec<u8>>,
+    // a body sent as it is read, instead of `body`
+    stream: Option<Box<Read + Send>>,
+    // cookies to set, written as `Set-Cookie` headers when sent
+    cookies: CookieJar,
 }
 
 impl SapperResponse {
     pub fn new() -> SapperResponse {
         SapperResponse {
             status: StatusCode::Ok,
-            headers: Headers::new(),
+            headers: HeaderMap::new(),
             body: None,
+            stream: None,
+            cookies: CookieJar::new(),
         }
     }
 
@@ -28,15 +40,35 @@ impl SapperResponse {
     }
 
     /// get response headers ref
-    pub fn headers(&self) -> &Headers {
+    pub fn headers(&self) -> &HeaderMap {
         &self.headers
     }
 
     /// get response headers mut ref
-    pub fn headers_mut(&mut self) -> &mut Headers {
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
         &mut self.headers
     }
 
+    /// get the cookies to set on the client
+    pub fn cookies(&self) -> &CookieJar {
+        &self.cookies
+    }
+
+    /// get the cookies to set on the client, mut ref, e.g.
+    /// `res.cookies_mut().add(Cookie::new("theme", "dark"))`
+    pub fn cookies_mut(&mut self) -> &mut CookieJar {
+        &mut self.cookies
+    }
+
+    // write the cookies as `Set-Cookie` headers, one for each
+    pub(crate) fn write_cookies(&mut self) {
+        for cookie in self.cookies.iter() {
+            self.headers
+                .append_raw("Set-Cookie", cookie.to_string().into_bytes());
+        }
+        self.cookies = CookieJar::new();
+    }
+
     /// get response body mut ref
     pub fn body(&self) -> &Option<Vec<u8>> {
         &self.body
@@ -44,11 +76,127 @@ impl SapperResponse {
 
     /// write string to body
     pub fn write_body(&mut self, body: String) {
-        self.body = Some(body.as_bytes().to_vec())
+        self.body = Some(body.as_bytes().to_vec());
+        self.stream = None;
     }
 
     /// write raw u8 vec to body
     pub fn write_raw_body(&mut self, body: Vec<u8>) {
-        self.body = Some(body)
+        self.body = Some(body);
+        self.stream = None;
+    }
+
+    /// stream the body from `reader`, it is sent chunked as it is read,
+    /// for bodies too large to buffer, or made while being sent
+    pub fn write_stream<R: Read + Send + 'static>(&mut self, reader: R) {
+        self.stream = Some(Box::new(reader));
+        self.body = None;
+    }
+
+    /// whether the body is a stream
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// get the body stream, it is taken out, so a middleware can wrap it
+    /// and write it back
+    pub fn take_stream(&mut self) -> Option<Box<Read + Send>> {
+        self.stream.take()
+    }
+
+    /// a response in the format of `offers` the client prefers, `render`
+    /// makes the body in the chosen media type, `NotAcceptable` when the
+    /// client accepts none of them
+    ///
+    /// ```ignore
+    /// SapperResponse::negotiated(req, &["application/json", "text/html"], |media| {
+    ///     Ok(if media == "text/html" { html(&post) } else { json(&post) }.into_bytes())
+    /// })
+    /// ```
+    pub fn negotiated<F>(req: &SapperRequest, offers: &[&str], render: F) -> Result<SapperResponse>
+    where
+        F: FnOnce(&str) -> Result<Vec<u8>>,
+    {
+        let media = req.negotiate(offers)?;
+
+        let mut res = SapperResponse::new();
+        res.headers_mut()
+            .set_raw("Content-Type", vec![media.as_bytes().to_vec()]);
+        res.headers_mut().append_raw("Vary", b"Accept".to_vec());
+        res.write_raw_body(render(media)?);
+        Ok(res)
+    }
+}
+
+/// the response an error is answered with, a `NotFound` response has no body,
+/// so the app can fill in its 404 page
+impl From<Error> for SapperResponse {
+    fn from(err: Error) -> SapperResponse {
+        let mut res = SapperResponse::new();
+        match err {
+            Error::NotFound => {
+                res.set_status(StatusCode::NotFound);
+            }
+            Error::Break(info) => {
+                res.set_status(StatusCode::BadRequest);
+                res.write_body(info);
+            }
+            Error::Unauthorized => {
+                res.set_status(StatusCode::Unauthorized);
+                res.write_body("Unauthorized".to_owned());
+            }
+            Error::Forbidden => {
+                res.set_status(StatusCode::Forbidden);
+                res.write_body("Forbidden".to_owned());
+            }
+            Error::NotAcceptable => {
+                res.set_status(StatusCode::NotAcceptable);
+                res.write_body("Not Acceptable".to_owned());
+            }
+            Error::PayloadTooLarge => {
+                res.set_status(StatusCode::PayloadTooLarge);
+                res.write_body("Payload Too Large".to_owned());
+            }
+            Error::UnsupportedMediaType => {
+                res.set_status(StatusCode::UnsupportedMediaType);
+                res.write_body("Unsupported Media Type".to_owned());
+            }
+            Error::InternalServerError(info) => {
+                res.set_status(StatusCode::InternalServerError);
+                res.write_body(info);
+            }
+            Error::Found(new_uri) => {
+                res.set_status(StatusCode::Found);
+                res.headers_mut()
+                    .set_raw("Location", vec![new_uri.as_bytes().to_vec()]);
+                res.write_body("Found, Redirect".to_owned());
+            }
+            Error::TemporaryRedirect(new_uri) => {
+                res.set_status(StatusCode::TemporaryRedirect);
+                res.headers_mut()
+                    .set_raw("Location", vec![new_uri.as_bytes().to_vec()]);
+                res.write_body("Temporary Redirect".to_owned());
+            }
+            Error::Custom(ustr) => {
+                res.write_body(ustr);
+            }
+            Error::CustomHtml(html_str) => {
+                res.headers_mut()
+                    .set_raw("Content-Type", vec!["text/html".as_bytes().to_vec()]);
+                res.write_body(html_str);
+            }
+            Error::CustomJson(json_str) => {
+                res.headers_mut().set_raw(
+                    "Content-Type",
+                    vec!["application/x-javascript".as_bytes().to_vec()],
+                );
+                res.write_body(json_str);
+            }
+            _ => {
+                res.set_status(StatusCode::InternalServerError);
+                res.write_body("InternalServerError".to_owned());
+            }
+        }
+        res
     }
 }
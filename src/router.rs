@@ -1,35 +1,222 @@
-use hyper::method::Method;
+use http::Method;
+use std::any::Any;
 use std::collections::HashMap;
+use std::mem;
 use std::sync::Arc;
 
+use app::{MountPrefix, Result, SapperModule};
+use armor::{AfterArmor, BeforeArmor, ModuleArmor, SapperArmorType};
+use guard::SapperGuard;
 use handler::SapperHandler;
+use middleware::{MiddlewareStack, SapperMiddlewareType};
+use request::SapperRequest;
+use response::SapperResponse;
+use state::StateMap;
 
-type InnerRouter = HashMap<Method, Vec<(&'static str, Arc<Box<SapperHandler>>)>>;
+type InnerRouter = HashMap<Method, Vec<Endpoint>>;
+
+/// One route collected by the router
+pub struct Endpoint {
+    /// host pattern this route is restricted to, `None` for any host
+    pub host: Option<String>,
+    pub glob: String,
+    /// extra conditions for the route to match, besides method and path
+    pub guards: Vec<Arc<Box<SapperGuard>>>,
+    pub handler: Arc<Box<SapperHandler>>,
+}
 
 /// Sapper router struct
 pub struct SapperRouter {
     router: InnerRouter,
+    // middlewares, armors and filters wrapping only the routes of this router (group)
+    middlewares: MiddlewareStack,
+    guards: Vec<Arc<Box<SapperGuard>>>,
+    // typed states shared by the routes of this router (group)
+    state: StateMap,
+    // modules nested in this router, in the order added
+    nested: Vec<Arc<Box<SapperModule>>>,
 }
 
 impl SapperRouter {
     pub fn new() -> SapperRouter {
         SapperRouter {
             router: HashMap::new(),
+            middlewares: MiddlewareStack::new(),
+            guards: Vec::new(),
+            state: StateMap::new(),
+            nested: Vec::new(),
         }
     }
 
     /// basic router method
-    pub fn route<H>(&mut self, method: Method, glob: &'static str, handler: H) -> &mut SapperRouter
+    pub fn route<H>(&mut self, method: Method, glob: &str, handler: H) -> &mut SapperRouter
+    where
+        H: SapperHandler + 'static,
+    {
+        let endpoint = Endpoint {
+            host: None,
+            glob: glob.to_owned(),
+            guards: Vec::new(),
+            handler: Arc::new(Box::new(handler)),
+        };
+        self.add(method, endpoint)
+    }
+
+    /// like route, but the route only matches when `guard` passes, otherwise
+    /// matching falls through to the next candidate route
+    pub fn route_guarded<G, H>(
+        &mut self,
+        method: Method,
+        glob: &str,
+        guard: G,
+        handler: H,
+    ) -> &mut SapperRouter
     where
+        G: SapperGuard + 'static,
         H: SapperHandler + 'static,
     {
+        let endpoint = Endpoint {
+            host: None,
+            glob: glob.to_owned(),
+            guards: vec![Arc::new(Box::new(guard))],
+            handler: Arc::new(Box::new(handler)),
+        };
+        self.add(method, endpoint)
+    }
+
+    fn add(&mut self, method: Method, endpoint: Endpoint) -> &mut SapperRouter {
         self.router
             .entry(method)
             .or_insert(Vec::new())
-            .push((glob, Arc::new(Box::new(handler))));
+            .push(endpoint);
+        self
+    }
+
+    // move the routes of `group` into this router
+    fn merge(&mut self, prefix: &str, host: Option<&str>, mut group: SapperRouter) {
+        let prefix = prefix.trim_end_matches('/');
+        self.nested.extend(group.take_nested());
+
+        // the prefix is added to the mount prefix, for `url_for`, before
+        // the middlewares of the group see the request
+        let mut stack = MiddlewareStack::new();
+        if !prefix.is_empty() {
+            let group_prefix = prefix.to_owned();
+            stack.push_armor(Box::new(BeforeArmor(move |req: &mut SapperRequest| {
+                let mount_prefix = req.ext().get::<MountPrefix>().cloned().unwrap_or_default();
+                req.ext_mut()
+                    .insert::<MountPrefix>(mount_prefix + &group_prefix);
+                Ok(())
+            })));
+        }
+
+        for (method, endpoints) in group.into_router() {
+            for mut endpoint in endpoints {
+                endpoint.glob = prefix.to_owned() + &endpoint.glob;
+                if !stack.is_empty() {
+                    endpoint.handler = stack.wrap(endpoint.handler);
+                }
+                if endpoint.host.is_none() {
+                    endpoint.host = host.map(|host| host.to_owned());
+                }
+                self.add(method.clone(), endpoint);
+            }
+        }
+    }
+
+    /// armor of this router, wrapping each handler of it, middlewares, armors
+    /// and filters stack in the order added: `before` are executed in that
+    /// order, `after` in reverse
+    pub fn armor(&mut self, armor: SapperArmorType) -> &mut SapperRouter {
+        self.middlewares.push_armor(armor);
+        self
+    }
+
+    /// middleware of this router, wrapping each handler call of it
+    pub fn middleware(&mut self, middleware: SapperMiddlewareType) -> &mut SapperRouter {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// before filter of this router, will be executed before each handler of it
+    pub fn before<F>(&mut self, filter: F) -> &mut SapperRouter
+    where
+        F: Fn(&mut SapperRequest) -> Result<()> + 'static + Send + Sync,
+    {
+        self.armor(Box::new(BeforeArmor(filter)))
+    }
+
+    /// after filter of this router, will be executed after each handler of it
+    pub fn after<F>(&mut self, filter: F) -> &mut SapperRouter
+    where
+        F: Fn(&SapperRequest, &mut SapperResponse) -> Result<()> + 'static + Send + Sync,
+    {
+        self.armor(Box::new(AfterArmor(filter)))
+    }
+
+    /// guard of this router, each route of it only matches when the guard
+    /// passes, otherwise matching falls through to the next candidate route
+    pub fn guard<G>(&mut self, guard: G) -> &mut SapperRouter
+    where
+        G: SapperGuard + 'static,
+    {
+        self.guards.push(Arc::new(Box::new(guard)));
+        self
+    }
+
+    /// register a value shared by the routes of this router, handlers get it
+    /// by its type with `req.state::<T>()`, before the app managed one
+    pub fn manage<T: Any + Send + Sync>(&mut self, value: T) -> &mut SapperRouter {
+        self.state.insert(value);
+        self
+    }
+
+    /// route group, all routes added in `f` are prefixed by `prefix`,
+    /// wrapped by the middlewares, armors and filters and checked by the guards
+    /// added in `f`
+    pub fn group<F>(&mut self, prefix: &str, f: F) -> &mut SapperRouter
+    where
+        F: FnOnce(&mut SapperRouter),
+    {
+        let mut group = SapperRouter::new();
+        f(&mut group);
+        self.merge(prefix, None, group);
         self
     }
 
+    /// host group, all routes added in `f` only match requests to a host
+    /// matching `pattern`, such as `api.example.com` or `:tenant.example.com`,
+    /// labels starting with `:` are collected into `HostParams`
+    pub fn host<F>(&mut self, pattern: &str, f: F) -> &mut SapperRouter
+    where
+        F: FnOnce(&mut SapperRouter),
+    {
+        let mut group = SapperRouter::new();
+        f(&mut group);
+        self.merge("", Some(pattern), group);
+        self
+    }
+
+    /// nest a module in this router, as a group under `prefix`,
+    /// the module's before and after wrap only its own routes
+    pub fn nest(&mut self, prefix: &str, sm: Box<SapperModule>) -> Result<&mut SapperRouter> {
+        let sm = Arc::new(sm);
+        let mut result = Ok(());
+
+        self.group(prefix, |group| {
+            group.nested.push(sm.clone());
+            group.armor(Box::new(ModuleArmor(sm.clone())));
+            result = sm.router(group);
+        });
+
+        result.map(move |_| self)
+    }
+
+    /// take the modules nested in this router, outer ones before inner ones
+    pub(crate) fn take_nested(&mut self) -> Vec<Arc<Box<SapperModule>>> {
+        mem::replace(&mut self.nested, Vec::new())
+    }
+
     /// Like route, but specialized to the `Get` method.
     pub fn get<H: SapperHandler + 'static>(
         &mut self,
@@ -93,7 +280,127 @@ impl SapperRouter {
         self.route(Method::Options, glob, handler)
     }
 
-    pub fn into_router(&self) -> &InnerRouter {
-        &self.router
+    /// consume the router, get the routes with their handlers wrapped by
+    /// this router's filters
+    pub fn into_router(self) -> InnerRouter {
+        if self.middlewares.is_empty() && self.guards.is_empty() && self.state.is_empty() {
+            return self.router;
+        }
+
+        // the state is visible from the outermost middleware on
+        let mut middlewares = MiddlewareStack::new();
+        if !self.state.is_empty() {
+            let state = Arc::new(self.state);
+            middlewares.push_armor(Box::new(BeforeArmor(move |req: &mut SapperRequest| {
+                req.add_state(state.clone());
+                Ok(())
+            })));
+        }
+        middlewares.extend(&self.middlewares);
+        let guards = self.guards;
+        let mut router = HashMap::new();
+        for (method, endpoints) in self.router {
+            let endpoints = endpoints
+                .into_iter()
+                .map(|mut endpoint| {
+                    endpoint.guards.extend(guards.iter().cloned());
+                    endpoint.handler = middlewares.wrap(endpoint.handler);
+                    endpoint
+                })
+                .collect();
+            router.insert(method, endpoints);
+        }
+        router
+    }
+}
+
+#[cfg(test)]
+use app::{Error, SapperApp};
+#[cfg(test)]
+use hyper::status::StatusCode;
+#[cfg(test)]
+use test_client::{trace, trace_after, traced, TestClient};
+
+#[cfg(test)]
+struct Shop;
+
+#[cfg(test)]
+impl SapperModule for Shop {
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        trace(req, "shop");
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router.get("/items", traced);
+        router.group("/admin", |admin| {
+            admin
+                .before(|req: &mut SapperRequest| {
+                    if !req.headers().contains("X-Admin") {
+                        return Err(Error::Unauthorized);
+                    }
+                    trace(req, "admin");
+                    Ok(())
+                })
+                .after(|req: &SapperRequest, res: &mut SapperResponse| {
+                    trace_after(res, "admin after");
+                    Ok(())
+                })
+                .get("/items", traced);
+            admin.group("/audit", |audit| {
+                audit.get("/log", traced);
+            });
+        });
+        router.nest("/blog", Box::new(Journal))?;
+        Ok(())
     }
 }
+
+#[cfg(test)]
+struct Journal;
+
+#[cfg(test)]
+impl SapperModule for Journal {
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        trace(req, "journal");
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router.get("/posts", traced);
+        Ok(())
+    }
+}
+
+#[test]
+fn groups_and_nested_modules() {
+    let mut app = SapperApp::new();
+    app.mount("/shop", Box::new(Shop));
+    let client = TestClient::new(app);
+
+    // the group filters wrap the group routes only
+    assert_eq!(client.get("/shop/items").send().text(), "shop,handler");
+    let res = client.get("/shop/admin/items").send();
+    assert_eq!(res.status(), StatusCode::Unauthorized);
+    let res = client
+        .get("/shop/admin/items")
+        .header("X-Admin", "1")
+        .send();
+    assert_eq!(res.text(), "shop,admin,handler,admin after");
+    // groups in groups add up their prefixes and filters
+    let res = client
+        .get("/shop/admin/audit/log")
+        .header("X-Admin", "1")
+        .send();
+    assert_eq!(res.text(), "shop,admin,handler,admin after");
+    assert_eq!(
+        client.get("/shop/items/admin").send().status(),
+        StatusCode::NotFound
+    );
+
+    // a nested module runs inside the module it is nested in
+    assert_eq!(
+        client.get("/shop/blog/posts").send().text(),
+        "shop,journal,handler"
+    );
+}
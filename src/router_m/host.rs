@@ -0,0 +1,112 @@
+use recognizer::Params;
+
+#[derive(PartialEq, Clone, Debug)]
+enum Label {
+    Static(String),
+    Param(String),
+    Any,
+}
+
+/// A host pattern, such as `api.example.com` or `:tenant.example.com`.
+///
+/// Each dot separated label matches literally (case insensitive), a label
+/// starting with `:` captures one label, and `*` matches any one label.
+#[derive(PartialEq, Clone, Debug)]
+pub struct HostPattern {
+    labels: Vec<Label>,
+}
+
+impl HostPattern {
+    pub fn new(pattern: &str) -> HostPattern {
+        let labels = trim_host(pattern)
+            .split('.')
+            .map(|label| {
+                if label == "*" {
+                    Label::Any
+                } else if label.len() > 0 && label.as_bytes()[0] == b':' {
+                    Label::Param(label[1..].to_owned())
+                } else {
+                    Label::Static(label.to_lowercase())
+                }
+            })
+            .collect();
+
+        HostPattern { labels }
+    }
+
+    /// count of labels which are not matched literally
+    pub fn dynamics(&self) -> usize {
+        self.labels
+            .iter()
+            .filter(|label| !matches!(**label, Label::Static(_)))
+            .count()
+    }
+
+    /// match `host` against this pattern, return the captured labels
+    pub fn recognize(&self, host: &str) -> Option<Params> {
+        let host = trim_host(host).to_lowercase();
+        let labels: Vec<&str> = host.split('.').collect();
+        if labels.len() != self.labels.len() {
+            return None;
+        }
+
+        let mut params = Params::new();
+        for (label, value) in self.labels.iter().zip(labels.into_iter()) {
+            match *label {
+                Label::Static(ref s) if s == value => {}
+                Label::Param(ref name) if value.len() > 0 => {
+                    params.insert(name.clone(), value.to_owned());
+                }
+                Label::Any if value.len() > 0 => {}
+                _ => return None,
+            }
+        }
+
+        Some(params)
+    }
+}
+
+// strip port and the trailing dot of a fully qualified name
+fn trim_host(host: &str) -> &str {
+    let host = match host.rfind(':') {
+        Some(i)
+            if !host[i + 1..].is_empty() && host[i + 1..].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            &host[..i]
+        }
+        _ => host,
+    };
+    host.trim_end_matches('.')
+}
+
+#[test]
+fn static_host() {
+    let pattern = HostPattern::new("api.example.com");
+
+    assert_eq!(pattern.recognize("api.example.com"), Some(Params::new()));
+    assert_eq!(
+        pattern.recognize("API.Example.com:8080"),
+        Some(Params::new())
+    );
+    assert_eq!(pattern.recognize("admin.example.com"), None);
+    assert_eq!(pattern.recognize("example.com"), None);
+}
+
+#[test]
+fn captured_host() {
+    let pattern = HostPattern::new(":tenant.example.com");
+    let mut params = Params::new();
+    params.insert("tenant".to_owned(), "acme".to_owned());
+
+    assert_eq!(pattern.recognize("acme.example.com"), Some(params));
+    assert_eq!(pattern.recognize("a.b.example.com"), None);
+    assert_eq!(pattern.dynamics(), 1);
+}
+
+#[test]
+fn any_label_host() {
+    let pattern = HostPattern::new("*.example.com");
+
+    assert_eq!(pattern.recognize("www.example.com."), Some(Params::new()));
+    assert_eq!(pattern.recognize(".example.com"), None);
+}
@@ -1,8 +1,8 @@
 #![deny(missing_docs)]
 #![cfg_attr(test, deny(warnings))]
 
+mod host;
 mod router;
 
 pub use router_m::router::Router;
 // pub use router::router::NoRoute;
-pub use recognizer::Params;
@@ -2,28 +2,45 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use app::Error;
+use app::HostParams;
 use app::Key;
 use app::PathParams;
 use app::Result;
+use guard::SapperGuard;
 use handler::SapperHandler;
-use hyper::method::Method;
+use http::Method;
+use hyper::header::Host;
 use request::SapperRequest;
 use response::SapperResponse;
 
+use recognizer::Params;
 use recognizer::Router as Recognizer;
-use recognizer::{Match, Params};
+use router_m::host::HostPattern;
 
 impl Key for PathParams {
     type Value = Params;
 }
 
+impl Key for HostParams {
+    type Value = Params;
+}
+
+// A handler with the guards it requires, several routes can share one glob.
+struct Route {
+    guards: Vec<Arc<Box<dyn SapperGuard>>>,
+    handler: Arc<Box<dyn SapperHandler>>,
+}
+
 /// `Router` provides an interface for creating complex routes as middleware
 /// for the Iron framework.
 pub struct Router {
     // The routers, specialized by method.
-    routers: HashMap<Method, Recognizer<Arc<Box<dyn SapperHandler>>>>,
+    routers: HashMap<Method, Recognizer<Vec<Route>>>,
     // Routes that accept any method.
-    wildcard: Recognizer<Arc<Box<dyn SapperHandler>>>,
+    wildcard: Recognizer<Vec<Route>>,
+    // The routers restricted to a host pattern, specialized by method,
+    // ordered from the most specific pattern.
+    hosts: Vec<(HostPattern, HashMap<Method, Recognizer<Vec<Route>>>)>,
 }
 
 impl Router {
@@ -31,6 +48,7 @@ impl Router {
         Router {
             routers: HashMap::new(),
             wildcard: Recognizer::new(),
+            hosts: Vec::new(),
         }
     }
 
@@ -43,22 +61,80 @@ impl Router {
     where
         S: AsRef<str>,
     {
-        self.routers
+        self.route_with(None, method, glob, Vec::new(), handler)
+    }
+
+    /// like route, but only matches requests to a host matching `host`
+    /// (any host when `None`) which pass all the `guards`
+    pub fn route_with<S>(
+        &mut self,
+        host: Option<String>,
+        method: Method,
+        glob: S,
+        guards: Vec<Arc<Box<dyn SapperGuard>>>,
+        handler: Arc<Box<dyn SapperHandler>>,
+    ) -> &mut Router
+    where
+        S: AsRef<str>,
+    {
+        let routers = match host {
+            Some(ref host) => {
+                let pattern = HostPattern::new(host);
+                let index = match self.hosts.iter().position(|&(ref p, _)| *p == pattern) {
+                    Some(index) => index,
+                    None => {
+                        let index = self
+                            .hosts
+                            .iter()
+                            .position(|&(ref p, _)| p.dynamics() > pattern.dynamics())
+                            .unwrap_or(self.hosts.len());
+                        self.hosts.insert(index, (pattern, HashMap::new()));
+                        index
+                    }
+                };
+                &mut self.hosts[index].1
+            }
+            None => &mut self.routers,
+        };
+
+        let routes = routers
             .entry(method)
             .or_insert(Recognizer::new())
-            .add(glob.as_ref(), handler);
+            .entry(glob.as_ref());
+        // an unguarded route replaces the one routed before, only guarded
+        // routes add up
+        if guards.is_empty() {
+            routes.retain(|route| !route.guards.is_empty());
+        }
+        routes.push(Route { guards, handler });
         self
     }
 
-    fn recognize(
-        &self,
-        method: &Method,
+    fn recognize<'a>(
+        &'a self,
+        req: &SapperRequest,
+        host: Option<&str>,
         path: &str,
-    ) -> Option<Match<&Arc<Box<dyn SapperHandler>>>> {
+    ) -> Option<(&'a Route, Params, Option<Params>)> {
+        if let Some(host) = host {
+            for &(ref pattern, ref routers) in self.hosts.iter() {
+                if let Some(host_params) = pattern.recognize(host) {
+                    let matched = routers
+                        .get(req.method())
+                        .and_then(|router| recognize_guarded(router, req, path));
+                    if let Some((route, params)) = matched {
+                        return Some((route, params, Some(host_params)));
+                    }
+                }
+            }
+        }
+
+        // unmatched hosts fall back to the routes for any host
         self.routers
-            .get(method)
-            .and_then(|router| router.recognize(path).ok())
-            .or(self.wildcard.recognize(path).ok())
+            .get(req.method())
+            .and_then(|router| recognize_guarded(router, req, path))
+            .or_else(|| recognize_guarded(&self.wildcard, req, path))
+            .map(|(route, params)| (route, params, None))
     }
 
     // fn handle_options(&self, path: &str) -> Response {
@@ -108,9 +184,18 @@ impl Router {
     // }
 
     pub fn handle_method(&self, req: &mut SapperRequest, path: &str) -> Result<SapperResponse> {
-        if let Some(matched) = self.recognize(req.method(), path) {
-            req.ext_mut().insert::<PathParams>(matched.params);
-            matched.handler.handle(req)
+        // the host of an absolute form target wins over the `Host` header
+        let host = match req.target().host() {
+            Some(host) => Some(host.to_owned()),
+            None => req.headers().get::<Host>().map(|host| host.hostname),
+        };
+        let recognized = self.recognize(req, host.as_ref().map(|h| &h[..]), path);
+        if let Some((route, params, host_params)) = recognized {
+            if let Some(host_params) = host_params {
+                req.ext_mut().insert::<HostParams>(host_params);
+            }
+            req.ext_mut().insert::<PathParams>(params);
+            route.handler.handle(req)
         } else {
             // panic!("router not matched!");
             // self.redirect_slash(req).and_then(|redirect| Some(Err(redirect)))
@@ -118,3 +203,20 @@ impl Router {
         }
     }
 }
+
+// the first route matching `path` whose guards all pass, candidates are
+// tried in priority order, routes sharing a glob in the order added
+fn recognize_guarded<'a>(
+    router: &'a Recognizer<Vec<Route>>,
+    req: &SapperRequest,
+    path: &str,
+) -> Option<(&'a Route, Params)> {
+    for matched in router.recognize_all(path) {
+        for route in matched.handler.iter() {
+            if route.guards.iter().all(|guard| guard.check(req)) {
+                return Some((route, matched.params));
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,46 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Shared state, one value per type, registered once and read by every request
+#[derive(Clone)]
+pub struct StateMap {
+    map: HashMap<TypeId, Arc<Any + Send + Sync>>,
+}
+
+impl StateMap {
+    pub fn new() -> StateMap {
+        StateMap {
+            map: HashMap::new(),
+        }
+    }
+
+    /// register `value` as the state of type `T`, replacing the former one
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// get the state of type `T`
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+#[test]
+fn state_by_type() {
+    struct Pool(u32);
+
+    let mut state = StateMap::new();
+    state.insert(Pool(5));
+    state.insert(String::from("config"));
+
+    assert_eq!(state.get::<Pool>().map(|pool| pool.0), Some(5));
+    assert_eq!(state.get::<String>().map(|s| &s[..]), Some("config"));
+    assert!(state.get::<u32>().is_none());
+}
@@ -0,0 +1,845 @@
+use std::io::Read;
+use std::net::SocketAddr;
+
+use headers::HeaderMap;
+use http::Method;
+use hyper::status::StatusCode;
+
+use app::SapperApp;
+use request::SapperRequest;
+use response::SapperResponse;
+
+/// An in process client for testing an app, requests are dispatched through
+/// the whole armor, module, router and static file pipeline without sockets
+///
+/// ```ignore
+/// let client = TestClient::new(app);
+/// let res = client.get("/foo").header("Accept", "text/html").send();
+/// assert_eq!(res.status(), StatusCode::Ok);
+/// ```
+pub struct TestClient {
+    app: SapperApp,
+}
+
+impl TestClient {
+    /// constructor, build the routers of `app`
+    pub fn new(mut app: SapperApp) -> TestClient {
+        app.build();
+        TestClient { app }
+    }
+
+    /// get the app under test
+    pub fn app(&self) -> &SapperApp {
+        &self.app
+    }
+
+    /// start a request of `method` to `path`, which may have a query string
+    pub fn request(&self, method: Method, path: &str) -> TestRequest {
+        TestRequest {
+            client: self,
+            method,
+            path: path.to_owned(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            remote_addr: "127.0.0.1:80".parse().unwrap(),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.request(Method::Get, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.request(Method::Post, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.request(Method::Put, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.request(Method::Delete, path)
+    }
+
+    pub fn head(&self, path: &str) -> TestRequest {
+        self.request(Method::Head, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.request(Method::Patch, path)
+    }
+
+    pub fn options(&self, path: &str) -> TestRequest {
+        self.request(Method::Options, path)
+    }
+}
+
+/// A synthetic request to send by a `TestClient`
+pub struct TestRequest<'c> {
+    client: &'c TestClient,
+    method: Method,
+    path: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    remote_addr: SocketAddr,
+}
+
+impl<'c> TestRequest<'c> {
+    /// set a header, replacing the values it had
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .set_raw(name.to_owned(), vec![value.as_bytes().to_vec()]);
+        self
+    }
+
+    /// set the body, its `Content-Length` is set when sending
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// set the address the request comes from
+    pub fn remote_addr(mut self, addr: SocketAddr) -> Self {
+        self.remote_addr = addr;
+        self
+    }
+
+    /// dispatch the request to the app, and get the response of it
+    pub fn send(self) -> TestResponse {
+        let mut sreq = SapperRequest::builder()
+            .method(self.method)
+            .uri(&self.path)
+            .headers(self.headers)
+            .body(self.body)
+            .remote_addr(self.remote_addr)
+            .build();
+
+        let mut response = self.client.app.dispatch(&mut sreq);
+        // a stream is read to the end, as the client would
+        if let Some(mut stream) = response.take_stream() {
+            let mut body = Vec::new();
+            stream.read_to_end(&mut body).unwrap();
+            response.write_raw_body(body);
+        }
+        TestResponse { response }
+    }
+}
+
+/// The response to a `TestRequest`, as the app would send it
+pub struct TestResponse {
+    response: SapperResponse,
+}
+
+impl TestResponse {
+    /// get response status
+    pub fn status(&self) -> StatusCode {
+        self.response.status()
+    }
+
+    /// get response headers ref
+    pub fn headers(&self) -> &HeaderMap {
+        self.response.headers()
+    }
+
+    /// get the raw value of a header, as a string
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.response
+            .headers()
+            .get_raw(name)
+            .and_then(|values| values.first())
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+    }
+
+    /// get the response body, empty when it has none
+    pub fn body(&self) -> &[u8] {
+        match *self.response.body() {
+            Some(ref body) => &body[..],
+            None => &[],
+        }
+    }
+
+    /// get the response body as a string
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(self.body()).into_owned()
+    }
+
+    /// get the whole response
+    pub fn into_inner(self) -> SapperResponse {
+        self.response
+    }
+}
+
+#[cfg(test)]
+use app::{Error, Result, SapperModule};
+#[cfg(test)]
+use router::SapperRouter;
+
+#[cfg(test)]
+struct Echo;
+
+#[cfg(test)]
+impl SapperModule for Echo {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router.post("/echo", |req: &mut SapperRequest| {
+            let mut res = SapperResponse::new();
+            let name = req
+                .headers()
+                .get_raw("X-Name")
+                .map(|values| String::from_utf8_lossy(&values[0]).into_owned())
+                .unwrap_or_default();
+            res.headers_mut()
+                .set_raw("X-Name", vec![name.as_bytes().to_vec()]);
+            res.write_raw_body(req.body().unwrap_or_default());
+            Ok(res)
+        });
+        router.get("/denied", |req: &mut SapperRequest| Err(Error::Forbidden));
+        router.get("/gone", |req: &mut SapperRequest| Err(Error::NotFound));
+        router.get("/", |req: &mut SapperRequest| {
+            let mut res = SapperResponse::new();
+            res.write_body("index".to_owned());
+            Ok(res)
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn test_client_dispatch() {
+    let mut app = SapperApp::new();
+    app.mount("/api", Box::new(Echo));
+    let client = TestClient::new(app);
+
+    let res = client
+        .post("/api/echo?x=1")
+        .header("X-Name", "sapper")
+        .body("hello")
+        .send();
+    assert_eq!(res.status(), StatusCode::Ok);
+    assert_eq!(res.header("X-Name"), Some("sapper".to_owned()));
+    assert_eq!(res.text(), "hello");
+
+    assert_eq!(
+        client.get("/api/denied").send().status(),
+        StatusCode::Forbidden
+    );
+
+    let res = client.get("/api/missing").send();
+    assert_eq!(res.status(), StatusCode::NotFound);
+    assert_eq!(res.text(), "404 Not Found");
+
+    // the module root is routed at the prefix, with or without the slash
+    assert_eq!(client.get("/api").send().text(), "index");
+    assert_eq!(client.get("/api/").send().text(), "index");
+}
+
+#[test]
+fn test_client_unbuilt_app() {
+    // served by hyper as it is, `Handler::handle` dispatches to an app
+    // never built
+    let mut app = SapperApp::new();
+    app.mount("/api", Box::new(Echo));
+
+    let mut req = SapperRequest::builder()
+        .method(Method::Post)
+        .uri("/api/echo")
+        .body("hello")
+        .build();
+    let res = app.dispatch(&mut req);
+    assert_eq!(res.status(), StatusCode::Ok);
+    assert_eq!(*res.body(), Some(b"hello".to_vec()));
+
+    let mut req = SapperRequest::builder().uri("/api/missing").build();
+    assert_eq!(app.dispatch(&mut req).status(), StatusCode::NotFound);
+}
+
+#[test]
+fn test_client_auto_options() {
+    let mut app = SapperApp::new();
+    app.mount("/api", Box::new(Echo));
+    let client = TestClient::new(app);
+
+    let res = client.options("/api/echo").send();
+    assert_eq!(res.status(), StatusCode::NoContent);
+    assert_eq!(res.header("Allow"), Some("OPTIONS, POST".to_owned()));
+    assert_eq!(
+        client.options("/api/missing").send().status(),
+        StatusCode::NotFound
+    );
+}
+
+#[cfg(test)]
+struct Locked;
+
+#[cfg(test)]
+impl SapperModule for Locked {
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        Err(Error::Forbidden)
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router.put("/echo", |req: &mut SapperRequest| Ok(SapperResponse::new()));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_client_auto_options_skip_module_armors() {
+    // the first module to route the glob is locked, the other is not
+    let mut app = SapperApp::new();
+    app.mount("/api", Box::new(Locked))
+        .mount("/api", Box::new(Echo));
+    let client = TestClient::new(app);
+
+    let res = client.options("/api/echo").send();
+    assert_eq!(res.status(), StatusCode::NoContent);
+    assert_eq!(res.header("Allow"), Some("OPTIONS, POST, PUT".to_owned()));
+    assert_eq!(client.post("/api/echo").send().status(), StatusCode::Ok);
+    assert_eq!(
+        client.put("/api/echo").send().status(),
+        StatusCode::Forbidden
+    );
+}
+
+#[test]
+fn test_client_request_decoding() {
+    use decompress::gzip;
+
+    let mut app = SapperApp::new();
+    app.mount("/api", Box::new(Echo)).max_body_size(1024);
+    let client = TestClient::new(app);
+
+    let res = client
+        .post("/api/echo")
+        .header("Content-Encoding", "gzip")
+        .body(gzip(b"hello"))
+        .send();
+    assert_eq!(res.text(), "hello");
+
+    let res = client
+        .post("/api/echo")
+        .header("Content-Encoding", "gzip")
+        .body(gzip(&[b'a'; 4096]))
+        .send();
+    assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+    assert_eq!(res.header("Connection"), Some("close".to_owned()));
+    let res = client.post("/api/echo").body(vec![b'a'; 4096]).send();
+    assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+    assert_eq!(res.header("Connection"), Some("close".to_owned()));
+
+    let res = client.post("/api/echo").body("hello").send();
+    assert_eq!(res.header("Connection"), None);
+}
+
+#[test]
+fn test_client_decode_errors_pass_middlewares() {
+    use armor::AfterArmor;
+
+    let mut app = SapperApp::new();
+    app.with_armor(Box::new(AfterArmor(
+        |req: &SapperRequest, res: &mut SapperResponse| {
+            res.headers_mut().set_raw("X-Tag", vec![b"global".to_vec()]);
+            Ok(())
+        },
+    )))
+    .mount("/api", Box::new(Echo))
+    .max_body_size(1024);
+    let client = TestClient::new(app);
+
+    // the body is decoded inside the global armor, which sees its errors
+    let res = client.post("/api/echo").body(vec![b'a'; 4096]).send();
+    assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+    assert_eq!(res.header("X-Tag"), Some("global".to_owned()));
+    assert_eq!(res.header("Connection"), Some("close".to_owned()));
+
+    let res = client
+        .post("/api/echo")
+        .header("Content-Encoding", "unknown")
+        .body("hello")
+        .send();
+    assert_eq!(res.status(), StatusCode::UnsupportedMediaType);
+    assert_eq!(res.header("X-Tag"), Some("global".to_owned()));
+}
+
+#[test]
+fn test_client_errors_pass_middlewares() {
+    use armor::AfterArmor;
+    use middleware::SapperMiddleware;
+
+    let mut app = SapperApp::new();
+    app.with_middleware(Box::new(
+        |req: &mut SapperRequest, next: &Fn(&mut SapperRequest) -> Result<SapperResponse>| {
+            match next(req) {
+                Err(Error::Forbidden) if req.headers().contains("X-Catch") => {
+                    let mut res = SapperResponse::new();
+                    res.write_body("caught".to_owned());
+                    Ok(res)
+                }
+                other => other,
+            }
+        },
+    ))
+    .with_armor(Box::new(AfterArmor(
+        |req: &SapperRequest, res: &mut SapperResponse| {
+            res.headers_mut().set_raw("X-After", vec![b"1".to_vec()]);
+            Ok(())
+        },
+    )))
+    .mount("/api", Box::new(Echo));
+    let client = TestClient::new(app);
+
+    // the outer middleware sees the error of the handler
+    let res = client.get("/api/denied").header("X-Catch", "1").send();
+    assert_eq!(res.status(), StatusCode::Ok);
+    assert_eq!(res.text(), "caught");
+
+    // uncaught, it is answered with the response the armor worked on
+    let res = client.get("/api/denied").send();
+    assert_eq!(res.status(), StatusCode::Forbidden);
+    assert_eq!(res.header("X-After"), Some("1".to_owned()));
+}
+
+#[test]
+fn test_client_not_routed_pass_middlewares() {
+    use armor::AfterArmor;
+
+    let mut app = SapperApp::new();
+    app.with_armor(Box::new(AfterArmor(
+        |req: &SapperRequest, res: &mut SapperResponse| {
+            res.headers_mut().set_raw("X-Tag", vec![b"global".to_vec()]);
+            Ok(())
+        },
+    )))
+    .not_found_page("nothing here".to_owned())
+    .mount("/api", Box::new(Echo));
+    let client = TestClient::new(app);
+
+    // the 404 of a request no route matches goes through the global armor,
+    // with the 404 page
+    let res = client.get("/missing").send();
+    assert_eq!(res.status(), StatusCode::NotFound);
+    assert_eq!(res.header("X-Tag"), Some("global".to_owned()));
+    assert_eq!(res.text(), "nothing here");
+}
+
+#[test]
+fn test_client_not_found_falls_to_static_files() {
+    use std::{env, fs, process};
+
+    // a directory of this test only, not to touch a `static` in the tree
+    let dir = env::temp_dir().join(format!("sapper-static-{}", process::id()));
+    fs::create_dir_all(dir.join("api")).unwrap();
+    fs::write(dir.join("api/gone"), "from the static files").unwrap();
+
+    let mut app = SapperApp::new();
+    app.static_dir(dir.to_str().unwrap())
+        .mount("/api", Box::new(Echo));
+    let res = TestClient::new(app).get("/api/gone").send();
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(res.status(), StatusCode::Ok);
+    assert_eq!(res.text(), "from the static files");
+}
+
+// records when it starts and shuts down, failing to start when told
+#[cfg(test)]
+struct Lifecycle(
+    &'static str,
+    ::std::sync::Arc<::std::sync::Mutex<Vec<String>>>,
+    bool,
+);
+
+#[cfg(test)]
+impl SapperModule for Lifecycle {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router.get("/ping", |req: &mut SapperRequest| Ok(SapperResponse::new()));
+        Ok(())
+    }
+
+    fn on_start(&self) -> Result<()> {
+        self.1.lock().unwrap().push(format!("start {}", self.0));
+        if self.2 {
+            return Err(Error::Custom("start failed".to_owned()));
+        }
+        Ok(())
+    }
+
+    fn on_shutdown(&self) -> Result<()> {
+        self.1.lock().unwrap().push(format!("shutdown {}", self.0));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_server_start_and_shutdown() {
+    use hyper::client::Client;
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut app = SapperApp::new();
+    app.address("127.0.0.1")
+        .port(0)
+        .mount("/a", Box::new(Lifecycle("a", events.clone(), false)))
+        .mount("/b", Box::new(Lifecycle("b", events.clone(), false)));
+    let server = app.start_http().unwrap();
+    assert_eq!(*events.lock().unwrap(), vec!["start a", "start b"]);
+
+    let url = format!("http://{}/a/ping", server.local_addr());
+    let res = Client::new().get(&url).send().unwrap();
+    assert_eq!(res.status, StatusCode::Ok);
+    drop(res);
+
+    server.shutdown();
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["start a", "start b", "shutdown b", "shutdown a"]
+    );
+    let res = Client::new().get(&url).send().unwrap();
+    assert_eq!(res.status, StatusCode::ServiceUnavailable);
+}
+
+#[test]
+fn test_server_start_failure() {
+    use std::sync::{Arc, Mutex};
+
+    // the modules started before the failing one are shut down again
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut app = SapperApp::new();
+    app.address("127.0.0.1")
+        .port(0)
+        .mount("/a", Box::new(Lifecycle("a", events.clone(), false)))
+        .mount("/b", Box::new(Lifecycle("b", events.clone(), true)))
+        .mount("/c", Box::new(Lifecycle("c", events.clone(), false)));
+    assert!(app.start_http().is_err());
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["start a", "start b", "shutdown a"]
+    );
+}
+
+// answers the url of `/x` under the prefixes of its route
+#[cfg(test)]
+fn url_of_x(req: &mut SapperRequest) -> Result<SapperResponse> {
+    let mut res = SapperResponse::new();
+    res.write_body(req.url_for("/x"));
+    Ok(res)
+}
+
+#[cfg(test)]
+struct Blog;
+
+#[cfg(test)]
+impl SapperModule for Blog {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router.get("/url", url_of_x);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+struct Links;
+
+#[cfg(test)]
+impl SapperModule for Links {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router.get("/url", url_of_x);
+        router.group("/v1", |v1| {
+            v1.get("/url", url_of_x);
+            v1.group("/admin/", |admin| {
+                admin.get("/url", url_of_x);
+            });
+        });
+        router.nest("/blog", Box::new(Blog))?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_client_url_for() {
+    let mut app = SapperApp::new();
+    app.mount("/api", Box::new(Links))
+        .add_module(Box::new(Blog));
+    let client = TestClient::new(app);
+
+    assert_eq!(client.get("/api/url").send().text(), "/api/x");
+    assert_eq!(client.get("/api/v1/url").send().text(), "/api/v1/x");
+    assert_eq!(
+        client.get("/api/v1/admin/url").send().text(),
+        "/api/v1/admin/x"
+    );
+    assert_eq!(client.get("/api/blog/url").send().text(), "/api/blog/x");
+    // not mounted
+    assert_eq!(client.get("/url").send().text(), "/x");
+}
+
+// the steps a request went through, in order
+#[cfg(test)]
+pub(crate) struct Trace;
+
+#[cfg(test)]
+impl ::app::Key for Trace {
+    type Value = Vec<String>;
+}
+
+#[cfg(test)]
+pub(crate) fn trace(req: &mut SapperRequest, step: &str) {
+    if req.ext().get::<Trace>().is_none() {
+        req.ext_mut().insert::<Trace>(Vec::new());
+    }
+    req.ext_mut()
+        .get_mut::<Trace>()
+        .unwrap()
+        .push(step.to_owned());
+}
+
+// the steps after the handler are added to the body
+#[cfg(test)]
+pub(crate) fn trace_after(res: &mut SapperResponse, step: &str) {
+    let body = res
+        .body()
+        .as_ref()
+        .map(|body| String::from_utf8_lossy(body).into_owned())
+        .unwrap_or_default();
+    res.write_body(body + "," + step);
+}
+
+// answers the steps so far
+#[cfg(test)]
+pub(crate) fn traced(req: &mut SapperRequest) -> Result<SapperResponse> {
+    trace(req, "handler");
+    let mut res = SapperResponse::new();
+    res.write_body(req.ext().get::<Trace>().unwrap().join(","));
+    Ok(res)
+}
+
+// traces its before and after, answers the requests with `X-Stop: <name>`
+#[cfg(test)]
+pub(crate) struct TraceArmor(pub(crate) &'static str);
+
+#[cfg(test)]
+impl ::armor::SapperArmor for TraceArmor {
+    fn before(&self, req: &mut SapperRequest) -> Result<()> {
+        trace(req, self.0);
+        Ok(())
+    }
+
+    fn after(&self, req: &SapperRequest, res: &mut SapperResponse) -> Result<()> {
+        trace_after(res, &format!("{} after", self.0));
+        Ok(())
+    }
+
+    fn intercept(&self, req: &mut SapperRequest) -> Result<Option<SapperResponse>> {
+        self.before(req)?;
+        let stop = req
+            .headers()
+            .get_str("X-Stop")
+            .map_or(false, |stop| stop == self.0);
+        if !stop {
+            return Ok(None);
+        }
+        let mut res = SapperResponse::new();
+        res.write_body(req.ext().get::<Trace>().unwrap().join(","));
+        Ok(Some(res))
+    }
+}
+
+#[cfg(test)]
+struct Users;
+
+#[cfg(test)]
+impl SapperModule for Users {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        router
+            .route_guarded(
+                Method::Get,
+                "/users/:id",
+                ::guard::query("by_id"),
+                |req: &mut SapperRequest| {
+                    let mut res = SapperResponse::new();
+                    res.write_body(format!(
+                        "id {}",
+                        req.ext().get::<::app::PathParams>().unwrap()["id"]
+                    ));
+                    Ok(res)
+                },
+            )
+            .get("/users/:name", |req: &mut SapperRequest| {
+                let mut res = SapperResponse::new();
+                res.write_body(format!(
+                    "name {}",
+                    req.ext().get::<::app::PathParams>().unwrap()["name"]
+                ));
+                Ok(res)
+            });
+        Ok(())
+    }
+}
+
+#[test]
+fn test_client_same_shape_routes() {
+    let mut app = SapperApp::new();
+    app.add_module(Box::new(Users));
+    let client = TestClient::new(app);
+
+    // each route keeps its own param names
+    assert_eq!(client.get("/users/7?by_id").send().text(), "id 7");
+    assert_eq!(client.get("/users/tom").send().text(), "name tom");
+}
+
+#[cfg(test)]
+struct Dup(&'static str);
+
+#[cfg(test)]
+impl SapperModule for Dup {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        let name = self.0;
+        router.get("/dup", move |req: &mut SapperRequest| {
+            let mut res = SapperResponse::new();
+            res.write_body(name.to_owned());
+            Ok(res)
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn test_client_duplicate_routes() {
+    let mut app = SapperApp::new();
+    app.add_module(Box::new(Dup("first")))
+        .add_module(Box::new(Dup("last")));
+    let client = TestClient::new(app);
+
+    // the route routed last replaces the one before
+    assert_eq!(client.get("/dup").send().text(), "last");
+}
+
+#[cfg(test)]
+fn text(body: String) -> Result<SapperResponse> {
+    let mut res = SapperResponse::new();
+    res.write_body(body);
+    Ok(res)
+}
+
+#[cfg(test)]
+struct Tenants;
+
+#[cfg(test)]
+impl SapperModule for Tenants {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        use app::HostParams;
+
+        // the dynamic pattern first, the static one still wins
+        router.host(":tenant.example.com", |router| {
+            router.get("/who", |req: &mut SapperRequest| {
+                let tenant = req.ext().get::<HostParams>().unwrap()["tenant"].clone();
+                text(format!("tenant {}", tenant))
+            });
+        });
+        router.host("admin.example.com", |router| {
+            router.get("/who", |req: &mut SapperRequest| text("admin".to_owned()));
+        });
+        router.get("/who", |req: &mut SapperRequest| text("any".to_owned()));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_client_host_routes() {
+    let mut app = SapperApp::new();
+    app.add_module(Box::new(Tenants));
+    let client = TestClient::new(app);
+
+    let who = |host: &str| client.get("/who").header("Host", host).send().text();
+    assert_eq!(who("acme.example.com"), "tenant acme");
+    assert_eq!(who("admin.example.com"), "admin");
+    // unmatched hosts fall back to the routes for any host
+    assert_eq!(who("example.org"), "any");
+    assert_eq!(client.get("/who").send().text(), "any");
+
+    // the host of an absolute form target is the one asked for
+    let res = client
+        .get("http://admin.example.com/who")
+        .header("Host", "acme.example.com")
+        .send();
+    assert_eq!(res.text(), "admin");
+}
+
+#[cfg(test)]
+struct Greeting(&'static str);
+
+#[cfg(test)]
+struct Stateful(&'static str, Option<&'static str>);
+
+#[cfg(test)]
+impl SapperModule for Stateful {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        if let Some(greeting) = self.1 {
+            router.manage(Greeting(greeting));
+        }
+        router.get(self.0, |req: &mut SapperRequest| {
+            text(req.state::<Greeting>()?.0.to_owned())
+        });
+        router.get("/count", |req: &mut SapperRequest| {
+            text(req.state::<u64>()?.to_string())
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn test_client_state() {
+    let mut app = SapperApp::new();
+    app.manage(Greeting("app"))
+        .add_module(Box::new(Stateful("/app", None)))
+        .add_module(Box::new(Stateful("/module", Some("module"))));
+    let client = TestClient::new(app);
+
+    assert_eq!(client.get("/app").send().text(), "app");
+    // the module state shadows the app one, for its routes only
+    assert_eq!(client.get("/module").send().text(), "module");
+
+    // a type not managed is an error
+    let res = client.get("/count").send();
+    assert_eq!(res.status(), StatusCode::InternalServerError);
+    assert_eq!(res.text(), "no state of type `u64` is managed");
+}
+
+#[cfg(test)]
+struct Probe(bool);
+
+#[cfg(test)]
+impl SapperModule for Probe {
+    fn router(&self, router: &mut SapperRouter) -> Result<()> {
+        Ok(())
+    }
+
+    fn health(&self) -> Result<()> {
+        if self.0 {
+            Ok(())
+        } else {
+            Err(Error::Custom("db down".to_owned()))
+        }
+    }
+}
+
+#[test]
+fn test_client_health_endpoint() {
+    let mut app = SapperApp::new();
+    app.health_endpoint("/health")
+        .add_module(Box::new(Probe(true)));
+    let res = TestClient::new(app).get("/health").send();
+    assert_eq!(res.status(), StatusCode::Ok);
+    assert_eq!(res.text(), "ok");
+
+    // the failures are labelled by the module and where it is mounted
+    let mut app = SapperApp::new();
+    app.health_endpoint("/health")
+        .add_module(Box::new(Probe(true)))
+        .mount("/db", Box::new(Probe(false)));
+    let res = TestClient::new(app).get("/health").send();
+    assert_eq!(res.status(), StatusCode::ServiceUnavailable);
+    assert_eq!(
+        res.text(),
+        "sapper::test_client::Probe at /db: Custom(\"db down\")"
+    );
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use hyper::uri::RequestUri;
+
+/// The target of a request, parsed once when the request comes.
+///
+/// All the target forms are understood: origin form (`/p?q`), absolute form
+/// (`http://host/p?q`, as sent to proxies), authority form (`host:port`,
+/// of `CONNECT`) and asterisk form (`*`, of `OPTIONS`). The path is
+/// normalized: `.` and `..` segments are resolved and double slashes merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Uri {
+    target: String,
+    scheme: Option<String>,
+    authority: Option<String>,
+    path: String,
+    query: Option<String>,
+}
+
+impl Uri {
+    pub fn new<S: Into<String>>(target: S) -> Uri {
+        let target = target.into();
+
+        let mut scheme = None;
+        let mut authority = None;
+        let rest = if target == "*" {
+            ""
+        } else if target.starts_with('/') {
+            &target[..]
+        } else if let Some(i) = scheme_end(&target) {
+            let after = &target[i + 3..];
+            let end = after.find(|c| c == '/' || c == '?').unwrap_or(after.len());
+            scheme = Some(target[..i].to_ascii_lowercase());
+            authority = Some(after[..end].to_owned());
+            &after[end..]
+        } else {
+            authority = Some(target.clone());
+            ""
+        };
+
+        // a fragment is not sent, but dropped when it is
+        let rest = rest.split('#').next().unwrap_or("");
+        let (path, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], Some(rest[i + 1..].to_owned())),
+            None => (rest, None),
+        };
+        let path = if target == "*" {
+            "*".to_owned()
+        } else {
+            normalize(path)
+        };
+
+        Uri {
+            target,
+            scheme,
+            authority,
+            path,
+            query,
+        }
+    }
+
+    /// the whole target, as sent
+    pub fn as_str(&self) -> &str {
+        &self.target
+    }
+
+    /// the scheme of an absolute form target, in lower case
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_ref().map(|s| &s[..])
+    }
+
+    /// the `host[:port]` of an absolute or authority form target
+    pub fn authority(&self) -> Option<&str> {
+        self.authority.as_ref().map(|s| &s[..])
+    }
+
+    /// the host of the authority, without user info and port
+    pub fn host(&self) -> Option<&str> {
+        self.authority().map(|authority| split_port(authority).0)
+    }
+
+    /// the port of the authority
+    pub fn port(&self) -> Option<u16> {
+        self.authority()
+            .and_then(|authority| split_port(authority).1)
+            .and_then(|port| port.parse().ok())
+    }
+
+    /// the normalized path, `/` when the target has none
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// the query string, without the `?`
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_ref().map(|s| &s[..])
+    }
+
+    /// whether the target is in absolute form
+    pub fn is_absolute(&self) -> bool {
+        self.scheme.is_some()
+    }
+}
+
+// the index of `://` when `target` starts with a scheme
+fn scheme_end(target: &str) -> Option<usize> {
+    let i = target.find("://")?;
+    let scheme = &target[..i];
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'-' || b == b'.');
+    if valid {
+        Some(i)
+    } else {
+        None
+    }
+}
+
+// split `[user@]host[:port]` into host and port
+fn split_port(authority: &str) -> (&str, Option<&str>) {
+    let authority = match authority.rfind('@') {
+        Some(i) => &authority[i + 1..],
+        None => authority,
+    };
+    // ipv6 literals are bracketed, `[::1]:8080`
+    let host_end = if authority.starts_with('[') {
+        authority
+            .find(']')
+            .map(|i| i + 1)
+            .unwrap_or(authority.len())
+    } else {
+        authority.find(':').unwrap_or(authority.len())
+    };
+    let port = authority[host_end..]
+        .strip_prefix(':')
+        .filter(|port| !port.is_empty());
+    (&authority[..host_end], port)
+}
+
+// resolve `.` and `..` segments and merge double slashes, a trailing
+// slash is kept
+fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    let trailing = path.ends_with('/') || path.ends_with("/.") || path.ends_with("/..");
+    let mut normalized = String::with_capacity(path.len() + 1);
+    for segment in segments.iter() {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if normalized.is_empty() || trailing {
+        normalized.push('/');
+    }
+    normalized
+}
+
+impl fmt::Display for Uri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.target)
+    }
+}
+
+impl From<RequestUri> for Uri {
+    fn from(uri: RequestUri) -> Uri {
+        match uri {
+            RequestUri::AbsolutePath(path) => Uri::new(path),
+            RequestUri::AbsoluteUri(url) => Uri::new(url.to_string()),
+            RequestUri::Authority(authority) => Uri::new(authority),
+            RequestUri::Star => Uri::new("*"),
+        }
+    }
+}
+
+#[test]
+fn origin_form() {
+    let uri = Uri::new("/a//b/./c/../d/?x=1&y=2");
+    assert_eq!(uri.path(), "/a/b/d/");
+    assert_eq!(uri.query(), Some("x=1&y=2"));
+    assert_eq!(uri.authority(), None);
+
+    assert_eq!(Uri::new("/../../etc/passwd").path(), "/etc/passwd");
+    assert_eq!(Uri::new("/a/b/..").path(), "/a/");
+    assert_eq!(Uri::new("/").path(), "/");
+    assert_eq!(Uri::new("/?").query(), Some(""));
+}
+
+#[test]
+fn absolute_form() {
+    let uri = Uri::new("HTTP://user@example.com:8080/p/q?r#s");
+    assert!(uri.is_absolute());
+    assert_eq!(uri.scheme(), Some("http"));
+    assert_eq!(uri.authority(), Some("user@example.com:8080"));
+    assert_eq!(uri.host(), Some("example.com"));
+    assert_eq!(uri.port(), Some(8080));
+    assert_eq!(uri.path(), "/p/q");
+    assert_eq!(uri.query(), Some("r"));
+
+    let uri = Uri::new("http://[::1]?x");
+    assert_eq!(uri.host(), Some("[::1]"));
+    assert_eq!(uri.port(), None);
+    assert_eq!(uri.path(), "/");
+    assert_eq!(uri.query(), Some("x"));
+}
+
+#[test]
+fn authority_and_asterisk_form() {
+    let uri = Uri::new("example.com:443");
+    assert_eq!(uri.host(), Some("example.com"));
+    assert_eq!(uri.port(), Some(443));
+    assert_eq!(uri.path(), "/");
+
+    assert_eq!(Uri::new("*").path(), "*");
+}
@@ -1,12 +1,21 @@
+use std::collections::HashMap;
+use std::sync::Once;
+
 use sapper::{Request, Response, Result};
 
 pub use sapper::PathParams;
 pub use sapper_body::FormParams;
 pub use sapper_body::JsonParams;
+pub use sapper_compression::CompressionArmor;
+pub use sapper_cors::CorsArmor;
+pub use sapper_csrf::{csrf_token, CsrfArmor, CsrfData, CsrfToken};
 pub use sapper_logger::splog;
 pub use sapper_query::QueryParams;
-pub use sapper_session::{set_cookie, SessionVal};
-pub use sapper_tmpl::{render, to_value, Context as WebContext, TeraResult, TeraValue, TERA};
+pub use sapper_session::{
+    set_cookie, set_signed_cookie, CookieKeys, FileStore, Flash, Level, MemoryStore, Session,
+    SessionArmor, SessionData, SessionExt, SessionStore, SessionVal,
+};
+pub use sapper_tmpl::{to_value, Context as WebContext, TeraResult, TeraValue, TERA};
 
 pub fn init(req: &mut Request, cookie_key: Option<&'static str>) -> Result<()> {
     sapper_logger::init(req)?;
@@ -23,6 +32,68 @@ pub fn finish(req: &Request, res: &mut Response) -> Result<()> {
     Ok(())
 }
 
+/// put the flash messages of the session in `context`, as `flashes`,
+/// a list of `{ level, message }`, empty when there is no session.
+///
+/// `render` and `res_html!($html, $context)` do not see the request, so
+/// their templates get no flashes: render with `res_html!($req, $html,
+/// $context)`, or pass the context to this first.
+pub fn flash_context(req: &Request, context: &mut WebContext) {
+    let flashes = req
+        .ext()
+        .get::<SessionData>()
+        .map(|session| session.flashes())
+        .unwrap_or(&[]);
+    context.insert("flashes", flashes);
+}
+
+/// put the csrf token of the request in `context`, as `csrf_token`, and
+/// as the hidden input of a form, `csrf_field`, to render with
+/// `{{ csrf_field | safe }}`, both empty when no `CsrfArmor` runs.
+///
+/// Tera functions do not see the request, so the token is only in the
+/// templates rendered by `res_html!($req, $html, $context)`, or with a
+/// context passed to this first. With the token in a context, the
+/// `csrf_input` function of `render` makes the hidden input of it.
+pub fn csrf_context(req: &Request, context: &mut WebContext) {
+    let (token, field) = csrf_token(req)
+        .map(|token| (token.value().to_owned(), token.hidden_input()))
+        .unwrap_or_default();
+    context.insert("csrf_token", &token);
+    context.insert("csrf_field", &field);
+}
+
+/// render the template `path` with `context`, like `sapper_tmpl::render`,
+/// with the tera functions of sapper_std registered:
+///
+/// - `csrf_input(token, field="csrf_token")`, the hidden input of a form
+///   for a csrf token, `{{ csrf_input(token=csrf_token) | safe }}`
+pub fn render(path: &str, context: WebContext) -> String {
+    static FUNCTIONS: Once = Once::new();
+    FUNCTIONS.call_once(|| {
+        TERA.write()
+            .unwrap()
+            .register_function("csrf_input", Box::new(csrf_input));
+    });
+
+    sapper_tmpl::render(path, context)
+}
+
+fn csrf_input(args: HashMap<String, TeraValue>) -> TeraResult<TeraValue> {
+    let token = match args.get("token").and_then(|token| token.as_str()) {
+        Some(token) => token,
+        None => return Err("csrf_input needs a `token` string".into()),
+    };
+    let field = args
+        .get("field")
+        .and_then(|field| field.as_str())
+        .unwrap_or("csrf_token");
+    Ok(to_value(format!(
+        "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+        field, token
+    ))?)
+}
+
 // ============ Status Code ============
 
 #[macro_export]
@@ -130,13 +201,25 @@ macro_rules! res_json_error {
 
 // ============ Page Render ============
 
+/// render `$context` by the template `$html`, with the request, the flash
+/// messages of the session are in the context as `flashes`, and the csrf
+/// token as `csrf_token` and `csrf_field`.
+///
+/// This is the only form putting them in the context, `res_html!($html,
+/// $context)` renders `$context` as it is.
 #[macro_export]
 macro_rules! res_html {
+    ($req:expr, $html:expr, $context:expr) => {{
+        let mut context = $context;
+        $crate::flash_context($req, &mut context);
+        $crate::csrf_context($req, &mut context);
+        res_html!($html, context)
+    }};
     ($html:expr, $context:expr) => {{
         use sapper::header::ContentType;
         use sapper::Response;
 
-        let res_str = render($html, $context);
+        let res_str = $crate::render($html, $context);
 
         let mut response = Response::new();
         response.headers_mut().set(ContentType::html());
@@ -146,6 +229,27 @@ macro_rules! res_html {
     }};
 }
 
+/// render `$context` by the template `$html`, or as json, as the
+/// client prefers, `NotAcceptable` when it accepts neither
+#[macro_export]
+macro_rules! res_negotiate {
+    ($req:expr, $html:expr, $context:expr) => {{
+        use sapper::Response;
+
+        let context = $context;
+        Response::negotiated($req, &["text/html", "application/json"], |media| {
+            if media == "text/html" {
+                Ok($crate::render($html, context).into_bytes())
+            } else {
+                context
+                    .as_json()
+                    .map(|json| json.to_string().into_bytes())
+                    .map_err(|err| sapper::Error::InternalServerError(err.to_string()))
+            }
+        })
+    }};
+}
+
 #[macro_export]
 macro_rules! res_html_before {
     ($context:expr) => {{
@@ -363,3 +467,108 @@ macro_rules! get_ext_owned {
         }
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapper::{App, Module, Router, TestClient};
+
+    struct Pages;
+
+    impl Module for Pages {
+        fn router(&self, router: &mut Router) -> Result<()> {
+            router.get("/form", |req: &mut Request| {
+                res_html!(req, "csrf_form.html", WebContext::new())
+            });
+            router.post("/save", |req: &mut Request| {
+                req.session()?.flash(Level::Success, "saved");
+                Ok(Response::new())
+            });
+            router.get("/flashes", |req: &mut Request| {
+                res_html!(req, "flashes.html", WebContext::new())
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_works() {}
+
+    #[test]
+    fn flash_context_without_session() {
+        let req = Request::builder().uri("/").build();
+        let mut context = WebContext::new();
+        flash_context(&req, &mut context);
+        assert_eq!(context.as_json().unwrap()["flashes"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn render_flashes() {
+        TERA.write()
+            .unwrap()
+            .add_raw_template(
+                "flashes.html",
+                "{% for flash in flashes %}{{ flash.level }}: {{ flash.message }}{% endfor %}",
+            )
+            .unwrap();
+
+        let mut app = App::new();
+        app.with_armor(Box::new(SessionArmor::new(MemoryStore::new())))
+            .add_module(Box::new(Pages));
+        let client = TestClient::new(app);
+
+        let res = client.post("/save").send();
+        let cookie = res.header("Set-Cookie").unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_owned();
+
+        let res = client.get("/flashes").header("Cookie", &cookie).send();
+        assert_eq!(res.text(), "success: saved");
+        // shown once
+        let res = client.get("/flashes").header("Cookie", &cookie).send();
+        assert_eq!(res.text(), "");
+    }
+
+    #[test]
+    fn render_csrf_field() {
+        TERA.write()
+            .unwrap()
+            .add_raw_template(
+                "csrf_form.html",
+                "<form method=\"post\">{{ csrf_field | safe }}</form>",
+            )
+            .unwrap();
+
+        let mut app = App::new();
+        app.with_armor(Box::new(CsrfArmor::double_submit()))
+            .add_module(Box::new(Pages));
+        let res = TestClient::new(app).get("/form").send();
+
+        let cookie = res.header("Set-Cookie").unwrap();
+        let token = &cookie.split(';').next().unwrap()["csrf_token=".len()..];
+        assert_eq!(
+            res.text(),
+            format!(
+                "<form method=\"post\"><input type=\"hidden\" name=\"csrf_token\" value=\"{}\"></form>",
+                token
+            )
+        );
+    }
+
+    #[test]
+    fn render_csrf_input() {
+        TERA.write()
+            .unwrap()
+            .add_raw_template(
+                "csrf_input.html",
+                "{{ csrf_input(token=csrf_token) | safe }}{{ csrf_input(token=csrf_token, field=\"_csrf\") | safe }}",
+            )
+            .unwrap();
+
+        let mut context = WebContext::new();
+        context.insert("csrf_token", "abc");
+        assert_eq!(
+            render("csrf_input.html", context),
+            "<input type=\"hidden\" name=\"csrf_token\" value=\"abc\"><input type=\"hidden\" name=\"_csrf\" value=\"abc\">"
+        );
+    }
+}